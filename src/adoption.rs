@@ -0,0 +1,365 @@
+use crate::template_model::{mapping, string, Resource, Template};
+use crate::Error;
+use aws_sdk_cloudformation::types::ResourceToImport;
+use serde_yaml::Value;
+
+/// The tag CloudFormation puts on everything it manages, naming the stack
+const STACK_NAME_TAG: &str = "aws:cloudformation:stack-name";
+
+///
+/// A hand-built site's bucket, as it is before we adopt it.
+///
+#[derive(Clone, Debug)]
+pub struct ExistingBucket {
+    pub name: String,
+    pub region: String,
+    /// The stack already managing the bucket, if there is one
+    pub stack_name: Option<String>,
+}
+
+///
+/// A hand-built site's distribution, as it is before we adopt it. Only the parts of its
+/// configuration we need to decide whether it can be adopted are kept.
+///
+#[derive(Clone, Debug)]
+pub struct ExistingDistribution {
+    pub id: String,
+    pub domain_name: String,
+    pub enabled: bool,
+    pub aliases: Vec<String>,
+    /// Each origin's ID and domain name
+    pub origins: Vec<(String, String)>,
+    pub default_origin_id: String,
+    pub viewer_protocol_policy: String,
+    /// How many cache behaviors there are besides the default
+    pub cache_behaviors: usize,
+    /// The stack already managing the distribution, if there is one
+    pub stack_name: Option<String>,
+}
+
+///
+/// Finds the stack managing a resource from its tags.
+///
+pub fn stack_from_tags<'a>(tags: impl IntoIterator<Item = (&'a str, &'a str)>) -> Option<String> {
+    tags.into_iter()
+        .find(|(key, _)| *key == STACK_NAME_TAG)
+        .map(|(_, value)| value.to_string())
+}
+
+///
+/// Checks a bucket and distribution can be brought under a site's stack without the update that
+/// follows the import changing what the site serves. Each problem found is described in the
+/// error; `warnings` gets anything that will change, but needn't stop the adoption.
+///
+pub fn check_compatible(
+    bucket: &ExistingBucket,
+    distribution: &ExistingDistribution,
+    site_names: &[String],
+    bucket_region: &str,
+    warnings: &mut Vec<String>,
+) -> Result<(), Error> {
+    let mut problems: Vec<String> = vec![];
+
+    if bucket.region != *bucket_region {
+        problems.push(format!(
+            "bucket {} is in {}, but the site's stack would be in {}; pass --bucket-region {}",
+            bucket.name, bucket.region, bucket_region, bucket.region
+        ));
+    }
+    if let Some(stack_name) = &bucket.stack_name {
+        problems.push(format!(
+            "bucket {} already belongs to the stack {}",
+            bucket.name, stack_name
+        ));
+    }
+    if let Some(stack_name) = &distribution.stack_name {
+        problems.push(format!(
+            "distribution {} already belongs to the stack {}",
+            distribution.id, stack_name
+        ));
+    }
+
+    // We serve the bucket through its REST endpoint. Its website endpoint does its own routing -
+    // index documents in subdirectories, redirects - which the site would lose.
+    let bucket_origin = distribution
+        .origins
+        .iter()
+        .find(|(_, domain_name)| is_rest_endpoint(domain_name, &bucket.name));
+    match bucket_origin {
+        Some((origin_id, _)) => {
+            if *origin_id != distribution.default_origin_id {
+                problems.push(format!(
+                    "distribution {} sends requests to {} by default, rather than bucket {}",
+                    distribution.id, distribution.default_origin_id, bucket.name
+                ));
+            }
+        }
+        None => problems.push(format!(
+            "distribution {} doesn't serve bucket {} through its REST endpoint, e.g. {}.s3.{}.amazonaws.com",
+            distribution.id, bucket.name, bucket.name, bucket.region
+        )),
+    }
+    let other_origins: Vec<&str> = distribution
+        .origins
+        .iter()
+        .filter(|(_, domain_name)| !is_rest_endpoint(domain_name, &bucket.name))
+        .map(|(_, domain_name)| domain_name.as_str())
+        .collect();
+    if !other_origins.is_empty() {
+        problems.push(format!(
+            "distribution {} also serves {}, which the site's template doesn't",
+            distribution.id,
+            other_origins.join(", ")
+        ));
+    }
+
+    // Any name the distribution serves that the site doesn't would be dropped by the update
+    for alias in &distribution.aliases {
+        if !site_names
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case(alias.trim_end_matches('.')))
+        {
+            problems.push(format!(
+                "distribution {} serves {}, which isn't one of the site's names; pass it with --alias",
+                distribution.id, alias
+            ));
+        }
+    }
+
+    if distribution.cache_behaviors > 0 {
+        warnings.push(format!(
+            "distribution {}'s {} cache behavior(s) will be replaced by the site's own",
+            distribution.id, distribution.cache_behaviors
+        ));
+    }
+    if !distribution.enabled {
+        warnings.push(format!(
+            "distribution {} is disabled, and will be enabled",
+            distribution.id
+        ));
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::IncompatibleResources { problems })
+    }
+}
+
+///
+/// Whether an origin domain is a bucket's REST endpoint - global, like
+/// `bucket.s3.amazonaws.com`, or regional, like `bucket.s3.eu-west-2.amazonaws.com` - rather than
+/// its website endpoint.
+///
+fn is_rest_endpoint(domain_name: &str, bucket_name: &str) -> bool {
+    let domain_name = domain_name.to_ascii_lowercase();
+    match domain_name.strip_prefix(&format!("{}.", bucket_name)) {
+        Some(endpoint) => {
+            (endpoint.starts_with("s3.") || endpoint.starts_with("s3-"))
+                && !endpoint.starts_with("s3-website")
+                && !endpoint.contains(".s3-website")
+        }
+        None => false,
+    }
+}
+
+///
+/// Renders the template for importing a bucket and distribution into a new stack. CloudFormation
+/// won't create anything during an import, so this has just the two resources, described as they
+/// already are; the site's own template is applied in an update afterwards. Imported resources
+/// need a deletion policy, and are retained should the import be rolled back.
+///
+pub fn render_import_template(
+    bucket: &ExistingBucket,
+    distribution: &ExistingDistribution,
+) -> Template {
+    let mut template = Template::default();
+
+    let mut website_bucket = Resource::new(
+        "AWS::S3::Bucket",
+        mapping(vec![("BucketName", string(&bucket.name))]),
+    );
+    website_bucket.deletion_policy = Some("Retain".to_string());
+    template.add_resource("WebsiteBucket", website_bucket);
+
+    let mut distribution_config = mapping(vec![
+        ("Enabled", Value::Bool(distribution.enabled)),
+        (
+            "Origins",
+            Value::Sequence(
+                distribution
+                    .origins
+                    .iter()
+                    .map(|(id, domain_name)| {
+                        mapping(vec![
+                            ("Id", string(id)),
+                            ("DomainName", string(domain_name)),
+                            ("S3OriginConfig", mapping(vec![])),
+                        ])
+                    })
+                    .collect(),
+            ),
+        ),
+        (
+            "DefaultCacheBehavior",
+            mapping(vec![
+                ("TargetOriginId", string(&distribution.default_origin_id)),
+                (
+                    "ViewerProtocolPolicy",
+                    string(&distribution.viewer_protocol_policy),
+                ),
+            ]),
+        ),
+    ]);
+    if !distribution.aliases.is_empty() {
+        distribution_config["Aliases"] =
+            Value::Sequence(distribution.aliases.iter().map(|a| string(a)).collect());
+    }
+    let mut cloudfront_distribution = Resource::new(
+        "AWS::CloudFront::Distribution",
+        mapping(vec![("DistributionConfig", distribution_config)]),
+    );
+    cloudfront_distribution.deletion_policy = Some("Retain".to_string());
+    template.add_resource("CloudfrontDistribution", cloudfront_distribution);
+
+    template
+}
+
+///
+/// The resources to import, matched up to their logical IDs in the import template.
+///
+pub fn resources_to_import(
+    bucket: &ExistingBucket,
+    distribution: &ExistingDistribution,
+) -> Vec<ResourceToImport> {
+    vec![
+        ResourceToImport::builder()
+            .resource_type("AWS::S3::Bucket")
+            .logical_resource_id("WebsiteBucket")
+            .resource_identifier("BucketName", &bucket.name)
+            .build(),
+        ResourceToImport::builder()
+            .resource_type("AWS::CloudFront::Distribution")
+            .logical_resource_id("CloudfrontDistribution")
+            .resource_identifier("Id", &distribution.id)
+            .build(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bucket() -> ExistingBucket {
+        ExistingBucket {
+            name: "example-site".to_string(),
+            region: "us-east-1".to_string(),
+            stack_name: None,
+        }
+    }
+
+    fn distribution() -> ExistingDistribution {
+        ExistingDistribution {
+            id: "E1234".to_string(),
+            domain_name: "d111111abcdef8.cloudfront.net".to_string(),
+            enabled: true,
+            aliases: vec!["example.com".to_string(), "www.example.com".to_string()],
+            origins: vec![(
+                "S3-example-site".to_string(),
+                "example-site.s3.us-east-1.amazonaws.com".to_string(),
+            )],
+            default_origin_id: "S3-example-site".to_string(),
+            viewer_protocol_policy: "redirect-to-https".to_string(),
+            cache_behaviors: 0,
+            stack_name: None,
+        }
+    }
+
+    fn names() -> Vec<String> {
+        vec!["example.com".to_string(), "www.example.com".to_string()]
+    }
+
+    fn problems(bucket: &ExistingBucket, distribution: &ExistingDistribution) -> Vec<String> {
+        match check_compatible(bucket, distribution, &names(), "us-east-1", &mut vec![]) {
+            Err(Error::IncompatibleResources { problems }) => problems,
+            other => panic!("expected problems, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn compatible_resources_can_be_adopted() {
+        let mut warnings = vec![];
+        check_compatible(
+            &bucket(),
+            &distribution(),
+            &names(),
+            "us-east-1",
+            &mut warnings,
+        )
+        .unwrap();
+        assert!(warnings.is_empty());
+
+        let mut global = distribution();
+        global.origins[0].1 = "example-site.s3.amazonaws.com".to_string();
+        global.cache_behaviors = 2;
+        check_compatible(&bucket(), &global, &names(), "us-east-1", &mut warnings).unwrap();
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn website_endpoints_and_other_origins_are_incompatible() {
+        let mut website = distribution();
+        website.origins[0].1 = "example-site.s3-website-us-east-1.amazonaws.com".to_string();
+        assert_eq!(problems(&bucket(), &website).len(), 2);
+
+        let mut api = distribution();
+        api.origins
+            .push(("Api".to_string(), "api.example.com".to_string()));
+        api.default_origin_id = "Api".to_string();
+        let problems = problems(&bucket(), &api);
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].contains("sends requests to Api by default"));
+        assert!(problems[1].contains("also serves api.example.com"));
+    }
+
+    #[test]
+    fn names_the_site_doesnt_have_are_incompatible() {
+        let mut extra = distribution();
+        extra.aliases.push("shop.example.com".to_string());
+        let problems = problems(&bucket(), &extra);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("shop.example.com"));
+    }
+
+    #[test]
+    fn managed_and_misplaced_resources_are_incompatible() {
+        let mut managed = bucket();
+        managed.stack_name = Some("Other".to_string());
+        managed.region = "eu-west-2".to_string();
+        let mut other_region = distribution();
+        other_region.origins[0].1 = "example-site.s3.eu-west-2.amazonaws.com".to_string();
+        other_region.stack_name =
+            stack_from_tags([("team", "web"), ("aws:cloudformation:stack-name", "Other")]);
+        assert_eq!(problems(&managed, &other_region).len(), 3);
+    }
+
+    #[test]
+    fn import_template_holds_just_the_imported_resources() {
+        let template = render_import_template(&bucket(), &distribution()).to_value();
+        let resources = template["Resources"].as_mapping().unwrap();
+        assert_eq!(resources.len(), 2);
+        for resource in resources.values() {
+            assert_eq!(resource["DeletionPolicy"], string("Retain"));
+        }
+        assert_eq!(
+            template["Resources"]["WebsiteBucket"]["Properties"]["BucketName"],
+            string("example-site")
+        );
+
+        let imports = resources_to_import(&bucket(), &distribution());
+        assert_eq!(
+            imports[1].logical_resource_id(),
+            Some("CloudfrontDistribution")
+        );
+    }
+}
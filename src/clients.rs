@@ -0,0 +1,89 @@
+use crate::partition::Partition;
+use crate::Error;
+use aws_config::{BehaviorVersion, Region};
+use tracing::info;
+
+///
+/// The AWS clients the commands work with. CloudFront, Route53 and the site's certificate live in
+/// the partition's control region; the site's bucket, and its stack, live in the bucket's region.
+///
+pub struct Clients {
+    pub control_region: &'static str,
+    pub bucket_region: String,
+    /// CloudFormation in the bucket's region, where the site's stack is
+    pub cfn: aws_sdk_cloudformation::Client,
+    /// CloudFormation in the control region, where the certificate's stack is, if it has one
+    pub certificate_cfn: aws_sdk_cloudformation::Client,
+    pub r53: aws_sdk_route53::Client,
+    pub sts: aws_sdk_sts::Client,
+    pub s3: aws_sdk_s3::Client,
+    pub cloudfront: aws_sdk_cloudfront::Client,
+}
+
+impl Clients {
+    ///
+    /// Sets up the clients for a site in the given partition, with its bucket in the given region,
+    /// or the control region if there isn't one. Credentials come from the given profile, or from
+    /// wherever the AWS SDK usually finds them.
+    ///
+    pub async fn new(
+        partition: Partition,
+        bucket_region: &Option<String>,
+        profile: Option<&str>,
+    ) -> Result<Self, Error> {
+        let (control_region, bucket_region) = regions(partition, bucket_region)?;
+        let control_config = load_config(control_region.to_string(), profile).await;
+        let site_config = load_config(bucket_region.clone(), profile).await;
+
+        Ok(Clients {
+            control_region,
+            bucket_region,
+            cfn: aws_sdk_cloudformation::Client::new(&site_config),
+            certificate_cfn: aws_sdk_cloudformation::Client::new(&control_config),
+            r53: aws_sdk_route53::Client::new(&control_config),
+            sts: aws_sdk_sts::Client::new(&control_config),
+            s3: aws_sdk_s3::Client::new(&site_config),
+            cloudfront: aws_sdk_cloudfront::Client::new(&control_config),
+        })
+    }
+
+    ///
+    /// Whether the site's certificate needs a stack of its own. CloudFront only takes certificates
+    /// from the control region, so a bucket anywhere else means a second stack there.
+    ///
+    pub fn separate_certificate(&self) -> bool {
+        self.bucket_region != self.control_region
+    }
+}
+
+///
+/// Works out where everything lives. CloudFront's certificate, and the global services, live in
+/// the partition's control region; the website's bucket can live anywhere in the partition.
+/// Returns the control region and the bucket region.
+///
+fn regions(
+    partition: Partition,
+    bucket_region: &Option<String>,
+) -> Result<(&'static str, String), Error> {
+    let control_region = partition.control_region();
+    let bucket_region = bucket_region
+        .clone()
+        .unwrap_or_else(|| control_region.to_string());
+    partition.validate(&bucket_region)?;
+
+    info!(
+        partition = partition.name(),
+        control_region = control_region,
+        bucket_region = &bucket_region,
+        "Using regions"
+    );
+    Ok((control_region, bucket_region))
+}
+
+pub async fn load_config(region: String, profile: Option<&str>) -> aws_config::SdkConfig {
+    let loader = aws_config::defaults(BehaviorVersion::latest()).region(Region::new(region));
+    match profile {
+        Some(profile) => loader.profile_name(profile).load().await,
+        None => loader.load().await,
+    }
+}
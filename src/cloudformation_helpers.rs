@@ -1,10 +1,19 @@
 use crate::{Error};
+use crate::polling::Backoff;
+use crate::stack_names::is_site_stack;
+use crate::tags::DOMAIN_TAG;
+use crate::template::{stack_policy, CERTIFICATE_LOGICAL_ID};
 use aws_sdk_cloudformation::operation::create_stack::CreateStackError;
-use aws_sdk_cloudformation::operation::update_stack::UpdateStackError;
-use aws_sdk_cloudformation::types::{Parameter, StackStatus};
-use std::time::Duration;
+use aws_sdk_cloudformation::types::{
+    ChangeSetStatus, ChangeSetType, Parameter, ResourceChange, ResourceStatus, ResourceToImport,
+    Stack, StackDriftDetectionStatus, StackEvent, StackResourceDetail, StackResourceDrift,
+    StackResourceDriftStatus, StackStatus, Tag, TemplateStage,
+};
+use aws_sdk_cloudformation::primitives::DateTimeFormat;
+use std::collections::{HashMap, HashSet};
+use std::time::{SystemTime, UNIX_EPOCH};
 use aws_sdk_cloudformation::error::SdkError;
-use tracing::{error, event, Level};
+use tracing::{error, event, info, warn, Level};
 use aws_sdk_cloudformation::error::ProvideErrorMetadata;
 
 ///
@@ -12,12 +21,19 @@ use aws_sdk_cloudformation::error::ProvideErrorMetadata;
 /// initial creation. If the stack enters an error or rollback state, an error will be
 /// returned.
 ///
+/// Stack events are logged as they happen, so progress - such as the certificate waiting on
+/// DNS validation, or the distribution deploying - can be followed while we wait.
+///
 /// This call should be wrapped in a timeout to ensure we don't spin our wheels indefinitely.
 ///
 pub async fn wait_for_stack(
-    stack_name: &String,
+    stack_name: &str,
     cfn_client: &aws_sdk_cloudformation::Client,
+    backoff: Backoff,
 ) -> Result<(), Error> {
+    let mut poller = backoff.start();
+    let mut seen_events: HashSet<String> = HashSet::new();
+
     loop {
         match cfn_client
             .describe_stacks()
@@ -33,13 +49,17 @@ pub async fn wait_for_stack(
                     .stack_status()
                     .expect("stack should have a status");
 
-                event!(Level::INFO, status = stack_status.as_str(), "Stack status");
+                // Log anything that's happened since we last looked
+                let events = current_operation_events(stack_name, cfn_client).await?;
+                log_new_events(&events, &mut seen_events);
 
                 // If it's an "end state" status, then we can return now based on what the status
                 // is. If the stack is still transitioning, we can pause a bit and check again.
                 let status: Option<Result<(), Error>> = match stack_status {
                     // Either of these means our change was approved
-                    StackStatus::CreateComplete | StackStatus::UpdateComplete => Some(Ok(())),
+                    StackStatus::CreateComplete
+                    | StackStatus::UpdateComplete
+                    | StackStatus::ImportComplete => Some(Ok(())),
 
                     // Any of these mean something bad has happened
                     StackStatus::CreateFailed
@@ -52,8 +72,15 @@ pub async fn wait_for_stack(
                     | StackStatus::RollbackFailed
                     | StackStatus::RollbackInProgress
                     | StackStatus::UpdateRollbackComplete
-                    | StackStatus::UpdateRollbackCompleteCleanupInProgress => {
-                        Some(Err(Error::UnusableStackStatus))
+                    | StackStatus::UpdateRollbackCompleteCleanupInProgress
+                    | StackStatus::ImportRollbackInProgress
+                    | StackStatus::ImportRollbackFailed
+                    | StackStatus::ImportRollbackComplete => {
+                        Some(Err(Error::StackFailed {
+                            stack_name: stack_name.to_string(),
+                            status: stack_status.as_str().to_string(),
+                            failures: failed_resources(stack_name, cfn_client).await,
+                        }))
                     }
 
                     // Any other status we're not done yet, and we should loop and wait
@@ -65,23 +92,69 @@ pub async fn wait_for_stack(
                 }
 
                 // Pause before we check again
-                tokio::time::sleep(Duration::from_secs(10)).await;
+                poller.wait().await;
             }
             Err(err) => {
-                return Err(Error::DescribeStacksError { source: err });
+                return Err(Error::from(err));
             }
         }
     }
 }
 
 ///
-/// Checks if a given stack exists and is a complete, successful state, returning true/false accordingly.
-/// If the function fails to find the stack, or the API call fails for some other reason, an Error will be returned.
+/// What a stack's status means for deploying to it.
 ///
-pub async fn stack_exists_and_is_complete(
-    stack_name: &String,
+#[derive(Clone, Debug, PartialEq)]
+pub enum StackState {
+    /// There's no such stack, so it needs creating
+    Missing,
+    /// The stack has settled into a state it can be updated from
+    Ready,
+    /// The stack is part way through an operation, and we need to wait for it to finish
+    InProgress(StackStatus),
+    /// The stack can't be updated, and the only way forward is to delete it - for instance, a
+    /// stack whose creation failed and was rolled back
+    Dead(StackStatus),
+    /// The stack can't be updated, and needs fixing by hand
+    Stuck(StackStatus),
+}
+
+impl From<&StackStatus> for StackState {
+    fn from(status: &StackStatus) -> Self {
+        match status {
+            StackStatus::CreateComplete
+            | StackStatus::UpdateComplete
+            | StackStatus::UpdateRollbackComplete
+            | StackStatus::ImportComplete => StackState::Ready,
+
+            // A stack whose import was rolled back holds nothing, as we only ever import into new
+            // stacks
+            StackStatus::CreateFailed
+            | StackStatus::ImportRollbackComplete
+            | StackStatus::RollbackComplete
+            | StackStatus::RollbackFailed
+            | StackStatus::ReviewInProgress => StackState::Dead(status.clone()),
+
+            other if other.as_str().ends_with("_IN_PROGRESS") => {
+                StackState::InProgress(status.clone())
+            }
+
+            // UPDATE_ROLLBACK_FAILED and friends need the rollback continuing, or resources
+            // skipping, which needs a human to decide on. A stack whose deletion failed may still
+            // be serving a site, so it's only ever deleted by destroy.
+            _ => StackState::Stuck(status.clone()),
+        }
+    }
+}
+
+///
+/// Works out the state of a given stack. If the API call fails for some reason other than the
+/// stack not existing, an Error will be returned.
+///
+pub async fn stack_state(
+    stack_name: &str,
     cfn_client: &aws_sdk_cloudformation::Client,
-) -> Result<bool, Error> {
+) -> Result<StackState, Error> {
     match cfn_client
         .describe_stacks()
         .stack_name(stack_name)
@@ -89,24 +162,11 @@ pub async fn stack_exists_and_is_complete(
         .await
     {
         Ok(stacks) => {
-            // Get the stack, and check that it is complete. If it is in a different state we can't
-            // update it.
             let stack = stacks
                 .stacks()
                 .first()
                 .expect("DescribeStacks with a response body should contain at least one stack");
-            match stack.stack_status().unwrap() {
-                StackStatus::CreateComplete |
-                StackStatus::UpdateComplete |
-                StackStatus::UpdateRollbackComplete => Ok(true),
-                other => {
-                    error!(
-                        stack_status = other.as_str(),
-                        "Got status and can't proceed"
-                    );
-                    Err(Error::UnusableStackStatus)
-                }
-            }
+            Ok(StackState::from(stack.stack_status().unwrap()))
         }
         Err(e) => {
             // TODO - clean this up, once the 'kind' on describe_stats structures the errors properly, rather
@@ -115,41 +175,129 @@ pub async fn stack_exists_and_is_complete(
             let svc_error = &e.message().expect("DescribeStacks contains an error message");
 
             return if svc_error.contains("does not exist") {
-                Ok(false)
+                Ok(StackState::Missing)
             } else {
-                Err(Error::DescribeStacksError { source: e })
+                Err(Error::from(e))
             };
         }
     }
 }
 
+///
+/// Waits for any operation the stack is part way through - perhaps another deploy, or a rollback -
+/// to finish, returning the state it settles into.
+///
+/// This call should be wrapped in a timeout.
+///
+pub async fn wait_for_stack_to_settle(
+    stack_name: &str,
+    cfn_client: &aws_sdk_cloudformation::Client,
+    backoff: Backoff,
+) -> Result<StackState, Error> {
+    let mut poller = backoff.start();
+    loop {
+        match stack_state(stack_name, cfn_client).await? {
+            StackState::InProgress(status) => {
+                info!(
+                    name = stack_name,
+                    status = status.as_str(),
+                    "Stack is busy; waiting for it to settle"
+                );
+                poller.wait().await;
+            }
+            settled => return Ok(settled),
+        }
+    }
+}
+
+///
+/// Finds the ID of the given stack, whatever state it's in, or None if there's no such stack.
+/// Unlike its name, the stack's ID still refers to it once it's been deleted.
+///
+pub async fn find_stack_id(
+    stack_name: &str,
+    cfn_client: &aws_sdk_cloudformation::Client,
+) -> Result<Option<String>, Error> {
+    match cfn_client
+        .describe_stacks()
+        .stack_name(stack_name)
+        .send()
+        .await
+    {
+        Ok(stacks) => Ok(stacks
+            .stacks()
+            .first()
+            .and_then(|stack| stack.stack_id())
+            .map(|stack_id| stack_id.to_string())),
+        Err(e) => {
+            // See stack_state - there's no structured error for a missing stack
+            if e.message().unwrap_or_default().contains("does not exist") {
+                Ok(None)
+            } else {
+                Err(Error::from(e))
+            }
+        }
+    }
+}
+
+///
+/// Retrieves the physical ID - for a bucket, its name - of one of the stack's resources, or None
+/// if the stack has no such resource, or it hasn't been created.
+///
+pub async fn stack_resource_id(
+    stack_id: &str,
+    logical_id: &str,
+    cfn_client: &aws_sdk_cloudformation::Client,
+) -> Result<Option<String>, Error> {
+    Ok(stack_resource(stack_id, logical_id, cfn_client)
+        .await?
+        .and_then(|resource| resource.physical_resource_id)
+        .filter(|physical_id| !physical_id.is_empty()))
+}
+
+///
+/// Describes one of the stack's resources, or None if the stack has no such resource.
+///
+pub async fn stack_resource(
+    stack_id: &str,
+    logical_id: &str,
+    cfn_client: &aws_sdk_cloudformation::Client,
+) -> Result<Option<StackResourceDetail>, Error> {
+    match cfn_client
+        .describe_stack_resource()
+        .stack_name(stack_id)
+        .logical_resource_id(logical_id)
+        .send()
+        .await
+    {
+        Ok(resource_response) => Ok(resource_response.stack_resource_detail),
+        Err(e) if e.message().unwrap_or_default().contains("does not exist") => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
 ///
 /// Creates the static website stack. This function returns once the CreateStack call has been made to the API,
-/// but does not wait for the stack to settle into a Complete state.
+/// but does not wait for the stack to settle into a Complete state. The tags are passed on to the
+/// stack's resources. A protected stack is created with termination protection and our stack
+/// policy.
 ///
 pub async fn create_stack(
-    stack_name: &String,
+    stack_name: &str,
     cfn_client: &aws_sdk_cloudformation::Client,
-    stack_body: &String,
-    zone_id: &String,
-    domain_name: &String,
+    stack_body: &str,
+    parameters: Vec<Parameter>,
+    tags: Vec<Tag>,
+    protect: bool,
 ) -> Result<String, SdkError<CreateStackError>> {
     let create_stack_response = cfn_client
         .create_stack()
         .stack_name(stack_name)
         .template_body(stack_body)
-        .parameters(
-            Parameter::builder()
-                .parameter_key("HostedZoneId")
-                .parameter_value(zone_id)
-                .build(),
-        )
-        .parameters(
-            Parameter::builder()
-                .parameter_key("DomainName")
-                .parameter_value(domain_name)
-                .build(),
-        )
+        .set_parameters(Some(parameters))
+        .set_tags(Some(tags))
+        .enable_termination_protection(protect)
+        .set_stack_policy_body(protect.then(|| stack_policy(true)))
         .send()
         .await?;
 
@@ -157,43 +305,362 @@ pub async fn create_stack(
 }
 
 ///
-/// Updates the stack. This function returns once the UpdateStack call has been made to the API,
-/// but does not wait for the stack to settle into a Complete state.
-pub async fn update_stack(
-    stack_name: &String,
+/// Creates a change set that would update the stack to the given template and parameters, and
+/// waits for CloudFormation to work out what's in it. If the update wouldn't change anything, the
+/// change set is deleted again and None is returned; otherwise the change set's ID is returned.
+/// The stack's tags are replaced with `tags`, or left as they are if it's None.
+///
+pub async fn create_change_set(
+    stack_name: &str,
+    cfn_client: &aws_sdk_cloudformation::Client,
+    stack_body: &str,
+    parameters: Vec<Parameter>,
+    tags: Option<Vec<Tag>>,
+    backoff: Backoff,
+) -> Result<Option<String>, Error> {
+    let created = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("the clock should be after the epoch")
+        .as_secs();
+    let create_response = cfn_client
+        .create_change_set()
+        .stack_name(stack_name)
+        .change_set_name(format!("staticwebsite-cli-{}", created))
+        .change_set_type(ChangeSetType::Update)
+        .template_body(stack_body)
+        .set_parameters(Some(parameters))
+        .set_tags(tags)
+        .send()
+        .await?;
+    let change_set_id = create_response
+        .id()
+        .expect("CreateChangeSet should return the change set's ID")
+        .to_string();
+
+    wait_for_change_set(change_set_id, cfn_client, backoff).await
+}
+
+///
+/// Creates a change set that creates the stack by importing existing resources into it, rather
+/// than creating any, and waits for CloudFormation to work out what's in it. Returns the change
+/// set's ID.
+///
+pub async fn create_import_change_set(
+    stack_name: &str,
     cfn_client: &aws_sdk_cloudformation::Client,
-    stack_body: &String,
-    zone_id: &String,
-    domain_name: &String,
-) -> Result<(), SdkError<UpdateStackError>> {
-    let update_stack_response = cfn_client
-        .update_stack()
+    stack_body: &str,
+    resources_to_import: Vec<ResourceToImport>,
+    tags: Vec<Tag>,
+    backoff: Backoff,
+) -> Result<String, Error> {
+    let created = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("the clock should be after the epoch")
+        .as_secs();
+    let create_response = cfn_client
+        .create_change_set()
         .stack_name(stack_name)
+        .change_set_name(format!("staticwebsite-cli-import-{}", created))
+        .change_set_type(ChangeSetType::Import)
         .template_body(stack_body)
-        .parameters(
+        .set_resources_to_import(Some(resources_to_import))
+        .set_tags(Some(tags))
+        .send()
+        .await?;
+    let change_set_id = create_response
+        .id()
+        .expect("CreateChangeSet should return the change set's ID")
+        .to_string();
+
+    wait_for_change_set(change_set_id, cfn_client, backoff)
+        .await?
+        .ok_or(Error::ChangeSetFailed {
+            reason: "the import didn't contain any resources".to_string(),
+        })
+}
+
+///
+/// Waits for CloudFormation to work out what's in a change set. Empty change sets are deleted, and
+/// None returned.
+///
+async fn wait_for_change_set(
+    change_set_id: String,
+    cfn_client: &aws_sdk_cloudformation::Client,
+    backoff: Backoff,
+) -> Result<Option<String>, Error> {
+    let mut poller = backoff.start();
+    loop {
+        let change_set = cfn_client
+            .describe_change_set()
+            .change_set_name(&change_set_id)
+            .send()
+            .await?;
+
+        match change_set.status() {
+            Some(ChangeSetStatus::CreateComplete) => return Ok(Some(change_set_id)),
+            Some(ChangeSetStatus::Failed) => {
+                // CloudFormation refuses to create empty change sets, and only tells us that's
+                // why in the status reason
+                let reason = change_set.status_reason().unwrap_or_default();
+                if reason.contains("didn't contain changes")
+                    || reason.contains("No updates are to be performed")
+                {
+                    delete_change_set(&change_set_id, cfn_client).await?;
+                    return Ok(None);
+                }
+                return Err(Error::ChangeSetFailed {
+                    reason: reason.to_string(),
+                });
+            }
+            _ => poller.wait().await,
+        }
+    }
+}
+
+///
+/// Retrieves the resource changes a change set would make.
+///
+pub async fn change_set_changes(
+    change_set_id: &str,
+    cfn_client: &aws_sdk_cloudformation::Client,
+) -> Result<Vec<ResourceChange>, Error> {
+    let mut changes: Vec<ResourceChange> = vec![];
+    let mut next_token: Option<String> = None;
+
+    loop {
+        let change_set = cfn_client
+            .describe_change_set()
+            .change_set_name(change_set_id)
+            .set_next_token(next_token.take())
+            .send()
+            .await?;
+
+        changes.extend(
+            change_set
+                .changes()
+                .iter()
+                .filter_map(|change| change.resource_change().cloned()),
+        );
+
+        match change_set.next_token() {
+            Some(token) => next_token = Some(token.to_string()),
+            None => return Ok(changes),
+        }
+    }
+}
+
+///
+/// Executes a change set. This function returns once the ExecuteChangeSet call has been made to
+/// the API, but does not wait for the stack to settle into a Complete state.
+///
+pub async fn execute_change_set(
+    change_set_id: &str,
+    cfn_client: &aws_sdk_cloudformation::Client,
+) -> Result<(), Error> {
+    cfn_client
+        .execute_change_set()
+        .change_set_name(change_set_id)
+        .send()
+        .await?;
+    Ok(())
+}
+
+///
+/// Deletes a change set we've decided not to execute.
+///
+pub async fn delete_change_set(
+    change_set_id: &str,
+    cfn_client: &aws_sdk_cloudformation::Client,
+) -> Result<(), Error> {
+    cfn_client
+        .delete_change_set()
+        .change_set_name(change_set_id)
+        .send()
+        .await?;
+    Ok(())
+}
+
+///
+/// Builds the parameters for a stack. The hosted zone is left out when DNS isn't managed in
+/// Route53, and the certificate ARN when the stack creates its own certificate, as the template
+/// then doesn't take them.
+///
+pub fn stack_parameters(
+    zone_id: Option<&str>,
+    domain_name: &str,
+    certificate_arn: Option<&str>,
+) -> Vec<Parameter> {
+    let mut parameters = vec![Parameter::builder()
+        .parameter_key("DomainName")
+        .parameter_value(domain_name)
+        .build()];
+    if let Some(zone_id) = zone_id {
+        parameters.push(
             Parameter::builder()
                 .parameter_key("HostedZoneId")
                 .parameter_value(zone_id)
                 .build(),
-        )
-        .parameters(
+        );
+    }
+    if let Some(certificate_arn) = certificate_arn {
+        parameters.push(
             Parameter::builder()
-                .parameter_key("DomainName")
-                .parameter_value(domain_name)
+                .parameter_key("CertificateArn")
+                .parameter_value(certificate_arn)
                 .build(),
-        )
-        .send()
-        .await;
+        );
+    }
+    parameters
+}
 
-    match update_stack_response {
+///
+/// Has CloudFormation check the template over. Problems with the template itself come back as an
+/// `InvalidTemplate` error, rather than a failed API call.
+///
+pub async fn validate_template(
+    template_body: &str,
+    cfn_client: &aws_sdk_cloudformation::Client,
+) -> Result<(), Error> {
+    match cfn_client
+        .validate_template()
+        .template_body(template_body)
+        .send()
+        .await
+    {
         Ok(_) => Ok(()),
+        Err(e) if e.code() == Some("ValidationError") => Err(Error::InvalidTemplate {
+            problems: vec![e.message().unwrap_or("rejected by CloudFormation").to_string()],
+        }),
+        Err(e) => Err(e.into()),
+    }
+}
+
+///
+/// Renders parameters as the JSON `aws cloudformation create-stack --parameters file://...`
+/// accepts, so a stack can be deployed with them outside the CLI.
+///
+pub fn parameters_json(parameters: &[Parameter]) -> String {
+    let parameters: Vec<serde_json::Value> = parameters
+        .iter()
+        .map(|parameter| {
+            serde_json::json!({
+                "ParameterKey": parameter.parameter_key(),
+                "ParameterValue": parameter.parameter_value(),
+            })
+        })
+        .collect();
+    serde_json::to_string_pretty(&parameters).expect("parameters should serialize") + "\n"
+}
+
+///
+/// What we report about a site's stack: where it's got to, and when it was last deployed.
+///
+pub struct StackSummary {
+    pub name: String,
+    pub status: StackStatus,
+    pub domain_name: Option<String>,
+    pub last_deployed: Option<String>,
+    pub protected: bool,
+    pub outputs: HashMap<String, String>,
+    pub tags: HashMap<String, String>,
+}
+
+impl From<&Stack> for StackSummary {
+    fn from(stack: &Stack) -> Self {
+        StackSummary {
+            name: stack.stack_name().unwrap_or_default().to_string(),
+            status: stack
+                .stack_status()
+                .cloned()
+                .expect("DescribeStacks should give the stack's status"),
+            domain_name: stack
+                .parameters()
+                .iter()
+                .find(|parameter| parameter.parameter_key() == Some("DomainName"))
+                .and_then(|parameter| parameter.parameter_value())
+                .or_else(|| {
+                    stack
+                        .tags()
+                        .iter()
+                        .find(|tag| tag.key() == Some(DOMAIN_TAG))
+                        .and_then(|tag| tag.value())
+                })
+                .map(|domain_name| domain_name.to_string()),
+            last_deployed: stack
+                .last_updated_time()
+                .or(stack.creation_time())
+                .and_then(|time| time.fmt(DateTimeFormat::DateTime).ok()),
+            protected: stack.enable_termination_protection().unwrap_or(false),
+            outputs: stack
+                .outputs()
+                .iter()
+                .filter_map(|output| {
+                    Some((
+                        output.output_key()?.to_string(),
+                        output.output_value()?.to_string(),
+                    ))
+                })
+                .collect(),
+            tags: stack
+                .tags()
+                .iter()
+                .filter_map(|tag| Some((tag.key()?.to_string(), tag.value()?.to_string())))
+                .collect(),
+        }
+    }
+}
+
+///
+/// Summarises the given stack, or returns None if there's no such stack.
+///
+pub async fn stack_summary(
+    stack_name: &str,
+    cfn_client: &aws_sdk_cloudformation::Client,
+) -> Result<Option<StackSummary>, Error> {
+    match cfn_client
+        .describe_stacks()
+        .stack_name(stack_name)
+        .send()
+        .await
+    {
+        Ok(stacks) => Ok(stacks.stacks().first().map(StackSummary::from)),
         Err(e) => {
-            let error_txt = format!("{:?}", e.message());
-            return if error_txt.to_string().contains("No updates are to be performed.") {
-                Ok(())
+            // See stack_state - there's no structured error for a missing stack
+            if e.message().unwrap_or_default().contains("does not exist") {
+                Ok(None)
             } else {
-                Err(e)
-            };
+                Err(Error::from(e))
+            }
+        }
+    }
+}
+
+///
+/// Summarises every site's stack in the client's region. Certificate stacks, and stacks that
+/// have nothing to do with us, are left out.
+///
+pub async fn site_stacks(
+    cfn_client: &aws_sdk_cloudformation::Client,
+) -> Result<Vec<StackSummary>, Error> {
+    let mut sites = vec![];
+    let mut next_token: Option<String> = None;
+    loop {
+        let stacks = cfn_client
+            .describe_stacks()
+            .set_next_token(next_token.take())
+            .send()
+            .await?;
+
+        for stack in stacks.stacks() {
+            let tagged = stack.tags().iter().any(|tag| tag.key() == Some(DOMAIN_TAG));
+            if is_site_stack(stack.stack_name().unwrap_or_default(), tagged) {
+                sites.push(StackSummary::from(stack));
+            }
+        }
+
+        match stacks.next_token() {
+            Some(token) => next_token = Some(token.to_string()),
+            None => return Ok(sites),
         }
     }
 }
@@ -203,9 +670,9 @@ pub async fn update_stack(
 /// or the API call fails.
 ///
 pub async fn get_stack_output(
-    stack_name: &String,
+    stack_name: &str,
     cfn_client: &aws_sdk_cloudformation::Client,
-    output_name: &String,
+    output_name: &str,
 ) -> Result<String, Error> {
     let describe_result = cfn_client
         .describe_stacks()
@@ -227,3 +694,515 @@ pub async fn get_stack_output(
 
     return Err(Error::StackOutputNotFound);
 }
+
+///
+/// Retrieves the events for the stack's most recent operation - everything from the point the
+/// stack last started creating, updating or deleting - oldest first.
+///
+pub async fn current_operation_events(
+    stack_name: &str,
+    cfn_client: &aws_sdk_cloudformation::Client,
+) -> Result<Vec<StackEvent>, Error> {
+    let mut events: Vec<StackEvent> = vec![];
+    let mut next_token: Option<String> = None;
+
+    // Events come back newest first, so we page backwards until we find the one that started it all
+    'pages: loop {
+        let events_response = cfn_client
+            .describe_stack_events()
+            .stack_name(stack_name)
+            .set_next_token(next_token.take())
+            .send()
+            .await?;
+
+        for stack_event in events_response.stack_events() {
+            events.push(stack_event.clone());
+
+            let is_stack_event = stack_event.logical_resource_id() == stack_event.stack_name();
+            let starts_operation = matches!(
+                stack_event.resource_status(),
+                Some(
+                    ResourceStatus::CreateInProgress
+                        | ResourceStatus::UpdateInProgress
+                        | ResourceStatus::DeleteInProgress
+                        | ResourceStatus::ImportInProgress
+                )
+            );
+            if is_stack_event && starts_operation {
+                break 'pages;
+            }
+        }
+
+        match events_response.next_token() {
+            Some(token) => next_token = Some(token.to_string()),
+            None => break,
+        }
+    }
+
+    events.reverse();
+    Ok(events)
+}
+
+///
+/// Describes the resources the stack's current operation is still waiting on - those whose most
+/// recent event leaves them in progress - along with anything CloudFormation has said about why.
+///
+pub async fn pending_resources(
+    stack_name: &str,
+    cfn_client: &aws_sdk_cloudformation::Client,
+) -> Result<Vec<String>, Error> {
+    let mut latest: HashMap<String, StackEvent> = HashMap::new();
+    for stack_event in current_operation_events(stack_name, cfn_client).await? {
+        if stack_event.logical_resource_id() == stack_event.stack_name() {
+            continue;
+        }
+        if let Some(resource) = stack_event.logical_resource_id() {
+            latest.insert(resource.to_string(), stack_event);
+        }
+    }
+
+    let mut pending: Vec<String> = latest
+        .iter()
+        .filter(|(_, stack_event)| {
+            stack_event
+                .resource_status()
+                .map(|status| status.as_str().ends_with("_IN_PROGRESS"))
+                .unwrap_or(false)
+        })
+        .map(|(resource, stack_event)| {
+            let status = stack_event.resource_status().map(|s| s.as_str()).unwrap_or_default();
+            match stack_event.resource_status_reason() {
+                Some(reason) if !reason.is_empty() => format!("{} {} ({})", resource, status, reason),
+                _ => format!("{} {}", resource, status),
+            }
+        })
+        .collect();
+    pending.sort();
+    Ok(pending)
+}
+
+///
+/// Runs drift detection on the stack and waits for it to finish, returning the resources that
+/// have been modified or deleted outside CloudFormation. Some resource types don't support drift
+/// detection; those are left out.
+///
+/// This call should be wrapped in a timeout.
+///
+pub async fn detect_drift(
+    stack_name: &str,
+    cfn_client: &aws_sdk_cloudformation::Client,
+    backoff: Backoff,
+) -> Result<Vec<StackResourceDrift>, Error> {
+    let mut poller = backoff.start();
+    let detection = cfn_client
+        .detect_stack_drift()
+        .stack_name(stack_name)
+        .send()
+        .await?;
+    let detection_id = detection
+        .stack_drift_detection_id()
+        .expect("DetectStackDrift should return a detection ID")
+        .to_string();
+
+    loop {
+        let detection_status = cfn_client
+            .describe_stack_drift_detection_status()
+            .stack_drift_detection_id(&detection_id)
+            .send()
+            .await?;
+
+        match detection_status.detection_status() {
+            Some(StackDriftDetectionStatus::DetectionComplete) => break,
+            // Failing for some resources still leaves us with results for the others
+            Some(StackDriftDetectionStatus::DetectionFailed) => {
+                warn!(
+                    reason = detection_status.detection_status_reason().unwrap_or_default(),
+                    "Drift detection failed for some resources"
+                );
+                break;
+            }
+            _ => poller.wait().await,
+        }
+    }
+
+    let mut drifts: Vec<StackResourceDrift> = vec![];
+    let mut next_token: Option<String> = None;
+    loop {
+        let drifts_response = cfn_client
+            .describe_stack_resource_drifts()
+            .stack_name(stack_name)
+            .stack_resource_drift_status_filters(StackResourceDriftStatus::Modified)
+            .stack_resource_drift_status_filters(StackResourceDriftStatus::Deleted)
+            .set_next_token(next_token.take())
+            .send()
+            .await?;
+        drifts.extend(drifts_response.stack_resource_drifts().iter().cloned());
+
+        match drifts_response.next_token() {
+            Some(token) => next_token = Some(token.to_string()),
+            None => return Ok(drifts),
+        }
+    }
+}
+
+///
+/// Retrieves the template the stack was last deployed with, as it was submitted.
+///
+pub async fn get_template(
+    stack_name: &str,
+    cfn_client: &aws_sdk_cloudformation::Client,
+) -> Result<String, Error> {
+    let template_response = cfn_client
+        .get_template()
+        .stack_name(stack_name)
+        .template_stage(TemplateStage::Original)
+        .send()
+        .await?;
+
+    Ok(template_response
+        .template_body()
+        .expect("GetTemplate should return the template")
+        .to_string())
+}
+
+///
+/// Checks whether termination protection is turned on for a stack.
+///
+pub async fn termination_protected(
+    stack_name: &str,
+    cfn_client: &aws_sdk_cloudformation::Client,
+) -> Result<bool, Error> {
+    let stacks = cfn_client
+        .describe_stacks()
+        .stack_name(stack_name)
+        .send()
+        .await?;
+
+    Ok(stacks
+        .stacks()
+        .first()
+        .expect("DescribeStacks should contain the stack")
+        .enable_termination_protection()
+        .unwrap_or(false))
+}
+
+///
+/// Turns a stack's protection on or off: both termination protection, and a stack policy
+/// stopping updates replacing or deleting the site's key resources.
+///
+pub async fn set_protection(
+    stack_name: &str,
+    protect: bool,
+    cfn_client: &aws_sdk_cloudformation::Client,
+) -> Result<(), Error> {
+    set_termination_protection(stack_name, protect, cfn_client).await?;
+    cfn_client
+        .set_stack_policy()
+        .stack_name(stack_name)
+        .stack_policy_body(stack_policy(protect))
+        .send()
+        .await?;
+    Ok(())
+}
+
+///
+/// Turns just a stack's termination protection on or off. Unlike its stack policy, this can be
+/// changed whatever state the stack is in.
+///
+pub async fn set_termination_protection(
+    stack_name: &str,
+    protect: bool,
+    cfn_client: &aws_sdk_cloudformation::Client,
+) -> Result<(), Error> {
+    cfn_client
+        .update_termination_protection()
+        .stack_name(stack_name)
+        .enable_termination_protection(protect)
+        .send()
+        .await?;
+    Ok(())
+}
+
+///
+/// Looks up the value a stack was deployed with for one of its parameters.
+///
+pub async fn stack_parameter_value(
+    stack_name: &str,
+    parameter_key: &str,
+    cfn_client: &aws_sdk_cloudformation::Client,
+) -> Result<Option<String>, Error> {
+    let stacks = cfn_client
+        .describe_stacks()
+        .stack_name(stack_name)
+        .send()
+        .await?;
+
+    Ok(stacks
+        .stacks()
+        .first()
+        .expect("DescribeStacks should contain the stack")
+        .parameters()
+        .iter()
+        .find(|parameter| parameter.parameter_key() == Some(parameter_key))
+        .and_then(|parameter| parameter.parameter_value())
+        .map(|value| value.to_string()))
+}
+
+///
+/// Builds parameters that keep each of the stack's current parameter values as they are.
+///
+pub async fn previous_parameters(
+    stack_name: &str,
+    cfn_client: &aws_sdk_cloudformation::Client,
+) -> Result<Vec<Parameter>, Error> {
+    let stacks = cfn_client
+        .describe_stacks()
+        .stack_name(stack_name)
+        .send()
+        .await?;
+
+    Ok(stacks
+        .stacks()
+        .first()
+        .expect("DescribeStacks should contain the stack")
+        .parameters()
+        .iter()
+        .filter_map(|parameter| parameter.parameter_key())
+        .map(|key| {
+            Parameter::builder()
+                .parameter_key(key)
+                .use_previous_value(true)
+                .build()
+        })
+        .collect())
+}
+
+///
+/// Deletes the stack. This function returns once the DeleteStack call has been made to the API,
+/// but does not wait for the deletion to complete.
+///
+pub async fn delete_stack(
+    stack_id: &str,
+    cfn_client: &aws_sdk_cloudformation::Client,
+) -> Result<(), Error> {
+    cfn_client.delete_stack().stack_name(stack_id).send().await?;
+    Ok(())
+}
+
+///
+/// Waits indefinitely for a stack to be deleted, logging its events as they happen. If the
+/// deletion fails, an error describing the resources that couldn't be deleted is returned.
+///
+/// The stack must be referred to by its ID, as its name stops resolving once it's deleted. This
+/// call should be wrapped in a timeout.
+///
+pub async fn wait_for_stack_deletion(
+    stack_id: &str,
+    cfn_client: &aws_sdk_cloudformation::Client,
+    backoff: Backoff,
+) -> Result<(), Error> {
+    let mut poller = backoff.start();
+    let mut seen_events: HashSet<String> = HashSet::new();
+
+    loop {
+        let stacks = cfn_client
+            .describe_stacks()
+            .stack_name(stack_id)
+            .send()
+            .await?;
+        let stack_status = stacks
+            .stacks()
+            .first()
+            .expect("stack should be present")
+            .stack_status()
+            .expect("stack should have a status");
+
+        let events = current_operation_events(stack_id, cfn_client).await?;
+        log_new_events(&events, &mut seen_events);
+
+        match stack_status {
+            StackStatus::DeleteComplete => return Ok(()),
+            StackStatus::DeleteFailed => {
+                return Err(Error::StackFailed {
+                    stack_name: stack_id.to_string(),
+                    status: stack_status.as_str().to_string(),
+                    failures: failed_resources(stack_id, cfn_client).await,
+                })
+            }
+            _ => poller.wait().await,
+        }
+    }
+}
+
+///
+/// Logs each of the operation's events that we haven't already, along with how far into the
+/// operation it happened. `events` should be oldest first.
+///
+fn log_new_events(events: &[StackEvent], seen_events: &mut HashSet<String>) {
+    let started = events.first().and_then(|e| e.timestamp()).map(|t| t.secs());
+
+    for stack_event in events {
+        let event_id = stack_event.event_id().unwrap_or_default().to_string();
+        if !seen_events.insert(event_id) {
+            continue;
+        }
+
+        let elapsed = match (started, stack_event.timestamp()) {
+            (Some(started), Some(timestamp)) => format!("{}s", timestamp.secs() - started),
+            _ => "unknown".to_string(),
+        };
+        event!(
+            Level::INFO,
+            resource = stack_event.logical_resource_id().unwrap_or_default(),
+            status = stack_event.resource_status().map(|s| s.as_str()).unwrap_or_default(),
+            reason = stack_event.resource_status_reason().unwrap_or_default(),
+            elapsed = elapsed,
+            "Stack event"
+        );
+    }
+}
+
+///
+/// Describes each resource that failed during the stack's most recent operation, along with the
+/// reason CloudFormation gave, logging them as we go. Resources that were merely cancelled because
+/// something else failed are left out. If the events can't be retrieved, we log that and carry on
+/// with none - the stack's status is still worth reporting.
+///
+async fn failed_resources(
+    stack_name: &str,
+    cfn_client: &aws_sdk_cloudformation::Client,
+) -> Vec<String> {
+    let events = match current_operation_events(stack_name, cfn_client).await {
+        Ok(events) => events,
+        Err(err) => {
+            warn!(error = %err, "Couldn't retrieve stack events");
+            return vec![];
+        }
+    };
+
+    let mut failures: Vec<String> = vec![];
+    for stack_event in events {
+        let failed = stack_event
+            .resource_status()
+            .map(|status| status.as_str().ends_with("_FAILED"))
+            .unwrap_or(false);
+        let reason = stack_event.resource_status_reason().unwrap_or("no reason given");
+        if !failed || reason.ends_with("cancelled") {
+            continue;
+        }
+
+        let resource = stack_event.logical_resource_id().unwrap_or("unknown resource");
+        let resource_type = stack_event.resource_type().unwrap_or("unknown type");
+        error!(
+            resource = resource,
+            resource_type = resource_type,
+            reason = reason,
+            "Resource failed"
+        );
+        failures.push(format!("{} ({}): {}", resource, resource_type, reason));
+    }
+    failures
+}
+
+///
+/// Waits for the stack's certificate to be validated, logging the DNS records that need to be
+/// created to validate it. This is for domains whose DNS isn't hosted in Route53 - the records
+/// have to be created by hand, and CloudFormation will sit waiting until they are.
+///
+/// Returns once the certificate has been issued (or has failed), or once the stack is no longer
+/// in progress. This call should be wrapped in a timeout.
+///
+pub async fn wait_for_certificate_validation(
+    stack_name: &str,
+    cfn_client: &aws_sdk_cloudformation::Client,
+    backoff: Backoff,
+) -> Result<(), Error> {
+    let mut poller = backoff.start();
+    let mut logged_records: HashSet<String> = HashSet::new();
+
+    loop {
+        let stacks = cfn_client
+            .describe_stacks()
+            .stack_name(stack_name)
+            .send()
+            .await?;
+        let in_progress = stacks
+            .stacks()
+            .first()
+            .and_then(|stack| stack.stack_status())
+            .map(|status| status.as_str().ends_with("_IN_PROGRESS"))
+            .unwrap_or(false);
+        if !in_progress {
+            return Ok(());
+        }
+
+        for stack_event in current_operation_events(stack_name, cfn_client).await? {
+            if stack_event.logical_resource_id() != Some(CERTIFICATE_LOGICAL_ID) {
+                continue;
+            }
+
+            if let Some(record) = stack_event
+                .resource_status_reason()
+                .and_then(parse_validation_record)
+            {
+                if logged_records.insert(record.name.clone()) {
+                    info!(
+                        name = record.name,
+                        r#type = record.record_type,
+                        value = record.value,
+                        "Create this DNS record to validate the certificate"
+                    );
+                }
+            }
+
+            match stack_event.resource_status() {
+                Some(ResourceStatus::CreateComplete | ResourceStatus::UpdateComplete) => {
+                    info!("Certificate validated");
+                    return Ok(());
+                }
+                Some(ResourceStatus::CreateFailed | ResourceStatus::UpdateFailed) => {
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+
+        poller.wait().await;
+    }
+}
+
+struct ValidationRecord {
+    name: String,
+    record_type: String,
+    value: String,
+}
+
+///
+/// CloudFormation reports the DNS record needed to validate a certificate in the certificate's
+/// status reason, in the form
+/// `Content of DNS Record is: {Name: _x.example.com.,Type: CNAME,Value: _y.acm-validations.aws.}`
+///
+fn parse_validation_record(reason: &str) -> Option<ValidationRecord> {
+    let (_, content) = reason.split_once("Content of DNS Record is:")?;
+    let content = content.trim().strip_prefix('{')?.split('}').next()?;
+
+    let mut name = None;
+    let mut record_type = None;
+    let mut value = None;
+    for field in content.split(',') {
+        let (key, field_value) = field.split_once(':')?;
+        let field_value = Some(field_value.trim().to_string());
+        match key.trim() {
+            "Name" => name = field_value,
+            "Type" => record_type = field_value,
+            "Value" => value = field_value,
+            _ => {}
+        }
+    }
+
+    Some(ValidationRecord {
+        name: name?,
+        record_type: record_type?,
+        value: value?,
+    })
+}
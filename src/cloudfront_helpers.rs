@@ -1,18 +1,22 @@
 use aws_sdk_cloudfront::operation::create_invalidation::CreateInvalidationError;
+use aws_sdk_cloudfront::operation::get_distribution::GetDistributionError;
 use aws_sdk_cloudfront::operation::get_invalidation::GetInvalidationError;
 use aws_sdk_cloudfront::Client;
-use std::time::Duration;
+use crate::Error;
+use crate::adoption::{stack_from_tags, ExistingDistribution};
+use crate::polling::Backoff;
 use std::time::{SystemTime, UNIX_EPOCH};
 use aws_sdk_cloudfront::error::SdkError;
 use aws_sdk_cloudfront::types::{InvalidationBatch, Paths};
 use tracing::info;
 
 ///
-/// Invalidates all objects in the distribution. This call does not wait for the invalidation to
-/// complete.
+/// Invalidates the given paths in the distribution - `/*` for all objects. This call does not wait
+/// for the invalidation to complete.
 ///
 pub async fn invalidate_distribution(
-    distribution_id: &String,
+    distribution_id: &str,
+    paths: &[String],
     cf_client: &Client,
 ) -> Result<String, SdkError<CreateInvalidationError>> {
     // Use current unix time as our invalidation reference
@@ -22,8 +26,8 @@ pub async fn invalidate_distribution(
         .as_millis()
         .to_string();
 
-    let invalidation_paths = Paths::builder().items("/*")
-        .quantity(1)
+    let invalidation_paths = Paths::builder().set_items(Some(paths.to_vec()))
+        .quantity(paths.len() as i32)
         .build()
         .expect("Valid invalidation paths");
 
@@ -52,11 +56,13 @@ pub async fn invalidate_distribution(
 /// in a timeout so we don't wait indefinitely.
 ///
 pub async fn wait_for_invalidation(
-    invalidation_id: &String,
-    distribution_id: &String,
+    invalidation_id: &str,
+    distribution_id: &str,
     cf_client: &Client,
+    backoff: Backoff,
 ) -> Result<(), SdkError<GetInvalidationError>> {
     info!("Waiting for invalidation to complete");
+    let mut poller = backoff.start();
     loop {
         let invalidation_output = cf_client
             .get_invalidation()
@@ -80,6 +86,164 @@ pub async fn wait_for_invalidation(
             _s => {}
         };
 
-        tokio::time::sleep(Duration::from_secs(10)).await;
+        poller.wait().await;
+    }
+}
+
+///
+/// Looks up the domain name CloudFront serves the distribution from.
+///
+pub async fn distribution_domain_name(
+    distribution_id: &str,
+    cf_client: &Client,
+) -> Result<String, SdkError<GetDistributionError>> {
+    let distribution_output = cf_client
+        .get_distribution()
+        .id(distribution_id)
+        .send()
+        .await?;
+
+    Ok(distribution_output
+        .distribution()
+        .expect("distribution body should be present")
+        .domain_name()
+        .to_string())
+}
+
+///
+/// Where a distribution has got to. CloudFront reports `InProgress` until its latest
+/// configuration has reached every edge location, and `Deployed` once it has.
+///
+pub struct DistributionState {
+    pub domain_name: String,
+    pub status: String,
+    pub enabled: bool,
+}
+
+///
+/// Looks up where a distribution has got to.
+///
+pub async fn distribution_state(
+    distribution_id: &str,
+    cf_client: &Client,
+) -> Result<DistributionState, SdkError<GetDistributionError>> {
+    let distribution_output = cf_client
+        .get_distribution()
+        .id(distribution_id)
+        .send()
+        .await?;
+    let distribution = distribution_output
+        .distribution()
+        .expect("distribution body should be present");
+
+    Ok(DistributionState {
+        domain_name: distribution.domain_name().to_string(),
+        status: distribution.status().to_string(),
+        enabled: distribution
+            .distribution_config()
+            .map(|config| config.enabled())
+            .unwrap_or(false),
+    })
+}
+
+///
+/// Checks the paths we've been asked to invalidate are ones CloudFront will take: each has to
+/// start with a `/`, and may end in a `*` wildcard. No paths means all of them.
+///
+pub fn invalidation_paths(paths: &[String]) -> Result<Vec<String>, Error> {
+    if paths.is_empty() {
+        return Ok(vec!["/*".to_string()]);
+    }
+
+    for path in paths {
+        if !path.starts_with('/') {
+            return Err(Error::InvalidInvalidationPath {
+                path: path.clone(),
+                reason: "it must start with a /".to_string(),
+            });
+        }
+        if path.strip_suffix('*').unwrap_or(path).contains('*') {
+            return Err(Error::InvalidInvalidationPath {
+                path: path.clone(),
+                reason: "a * wildcard can only come at the end".to_string(),
+            });
+        }
+    }
+    Ok(paths.to_vec())
+}
+
+///
+/// Looks up an existing distribution we've been asked to adopt, along with the stack managing it,
+/// if any.
+///
+pub async fn existing_distribution(
+    distribution_id: &str,
+    cf_client: &Client,
+) -> Result<ExistingDistribution, Error> {
+    let distribution_output = cf_client
+        .get_distribution()
+        .id(distribution_id)
+        .send()
+        .await?;
+    let distribution = distribution_output
+        .distribution()
+        .expect("distribution body should be present");
+    let config = distribution
+        .distribution_config()
+        .expect("distribution config should be present");
+
+    let tags_output = cf_client
+        .list_tags_for_resource()
+        .resource(distribution.arn())
+        .send()
+        .await?;
+    let tags = tags_output.tags().map(|t| t.items()).unwrap_or_default();
+    let stack_name = stack_from_tags(tags.iter().map(|t| (t.key(), t.value().unwrap_or_default())));
+
+    let default_cache_behavior = config.default_cache_behavior();
+    Ok(ExistingDistribution {
+        id: distribution.id().to_string(),
+        domain_name: distribution.domain_name().to_string(),
+        enabled: config.enabled(),
+        aliases: config.aliases().map(|a| a.items().to_vec()).unwrap_or_default(),
+        origins: config
+            .origins()
+            .map(|o| o.items())
+            .unwrap_or_default()
+            .iter()
+            .map(|origin| (origin.id().to_string(), origin.domain_name().to_string()))
+            .collect(),
+        default_origin_id: default_cache_behavior
+            .map(|b| b.target_origin_id().to_string())
+            .unwrap_or_default(),
+        viewer_protocol_policy: default_cache_behavior
+            .map(|b| b.viewer_protocol_policy().as_str().to_string())
+            .unwrap_or("redirect-to-https".to_string()),
+        cache_behaviors: config.cache_behaviors().map(|b| b.quantity()).unwrap_or(0) as usize,
+        stack_name,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(paths: &[&str]) -> Vec<String> {
+        paths.iter().map(|path| path.to_string()).collect()
+    }
+
+    #[test]
+    fn checks_invalidation_paths() {
+        assert_eq!(invalidation_paths(&[]).unwrap(), paths(&["/*"]));
+
+        let valid = paths(&["/index.html", "/blog/*", "/*", "/café", "/café/*", "/"]);
+        assert_eq!(invalidation_paths(&valid).unwrap(), valid);
+
+        for invalid in ["index.html", "", "/*/index.html", "/a**", "/café*/x"] {
+            assert!(matches!(
+                invalidation_paths(&paths(&["/ok", invalid])),
+                Err(Error::InvalidInvalidationPath { .. })
+            ));
+        }
     }
 }
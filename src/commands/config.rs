@@ -0,0 +1,47 @@
+use crate::config::{self, ConfigFile};
+use crate::error::Error;
+use tracing::info;
+
+///
+/// Checks the config file over, logging the settings each of its environments resolves to.
+///
+pub fn validate_config(config_file: &ConfigFile) -> Result<(), Error> {
+    let path = match &config_file.path {
+        Some(path) => path,
+        None => {
+            return Err(Error::NoConfigFile {
+                path: config::DEFAULT_CONFIG_FILE.to_string(),
+            })
+        }
+    };
+
+    for (name, environment) in &config_file.environments {
+        info!(
+            env = name,
+            domain_zone = environment
+                .domain_zone
+                .as_ref()
+                .or(config_file.defaults.domain_zone.as_ref()),
+            domain_name = environment
+                .domain_name
+                .as_ref()
+                .or(config_file.defaults.domain_name.as_ref()),
+            profile = environment
+                .profile
+                .as_ref()
+                .or(config_file.defaults.profile.as_ref()),
+            "Environment"
+        );
+    }
+
+    let problems = config_file.problems();
+    if problems.is_empty() {
+        info!(path = path, "The config file looks good");
+        Ok(())
+    } else {
+        Err(Error::InvalidConfigFile {
+            path: path.clone(),
+            problems,
+        })
+    }
+}
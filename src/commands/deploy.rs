@@ -0,0 +1,690 @@
+use super::{
+    delete_stack_and_wait, deployed_bucket_name, find_stack_names, invalidate_and_wait,
+    resolve_site, review_changes, wait_on_stack,
+};
+use crate::clients::Clients;
+use crate::cloudformation_helpers::{self, StackState};
+use crate::cloudfront_helpers;
+use crate::error::Error;
+use crate::s3_helpers::upload_directory;
+use crate::tags;
+use crate::template;
+use crate::{
+    adoption, prompt_helpers, route53_helpers, s3_helpers, template_validation, verify_helpers,
+    DeployArgs,
+};
+use aws_sdk_cloudformation::types::{Parameter, StackStatus, Tag};
+use std::fs;
+use std::future::Future;
+use std::path::Path;
+use std::time::Duration;
+use tokio::time::timeout;
+use tracing::{error, info, warn};
+
+pub async fn deploy(args: &DeployArgs) -> Result<(), Error> {
+    // These can come from the config file, so clap can't insist on them
+    let domain_zone = args.site.domain_zone()?;
+    let deploy_directory = args.deploy.as_ref().ok_or(Error::MissingSetting {
+        flag: "--deploy".to_string(),
+    })?;
+
+    let clients = Clients::new(
+        args.site.partition,
+        &args.site.bucket_region,
+        args.site.profile.as_deref(),
+    )
+    .await?;
+    let separate_certificate = clients.separate_certificate();
+
+    // Make sure the directory exists
+    let path = Path::new(deploy_directory);
+    let _ = fs::read_dir(path);
+
+    // Make sure we've got access
+    info!("Checking AWS access");
+    let _caller_identity = clients.sts.get_caller_identity().send().await?;
+    info!("AWS access looks good, continuing");
+
+    let mut site = resolve_site(&args.site, separate_certificate, &clients.r53).await?;
+    let fqdn = site.fqdn.clone();
+    let zone_id = site.zone_id.clone();
+    let site_names = site.names();
+
+    let stack_tags = tags::stack_tags(&args.config_tags, &args.tags, &args.preview_tags, &fqdn)?;
+
+    let stack_names = find_stack_names(
+        &fqdn,
+        args.site.stack_name.as_deref(),
+        &clients.cfn,
+        separate_certificate.then_some(&clients.certificate_cfn),
+    )
+    .await?;
+    let stack_name = stack_names.site.clone();
+    site.config.bucket_name = match &args.adopt_bucket {
+        Some(bucket) => Some(bucket.clone()),
+        None => deployed_bucket_name(&stack_name, &clients.cfn).await?,
+    };
+
+    let stack_template = match &args.template {
+        Some(path) => {
+            info!(path = path, "Using template from file");
+            fs::read_to_string(path)?
+        }
+        None => template::render_template(&fqdn, &site.config)?.to_yaml(),
+    };
+    let certificate_template = if separate_certificate {
+        Some(template::render_certificate_template(&site.config)?.to_yaml())
+    } else {
+        None
+    };
+
+    // Check the templates before changing anything, rather than finding out part way through
+    let mut supplied_parameters = vec!["DomainName".to_string()];
+    if zone_id.is_some() {
+        supplied_parameters.push("HostedZoneId".to_string());
+    }
+    if let Some(certificate_template) = &certificate_template {
+        validate_template(
+            certificate_template,
+            &template_validation::CERTIFICATE_OUTPUTS,
+            &supplied_parameters,
+            &clients.certificate_cfn,
+        )
+        .await?;
+        supplied_parameters.push("CertificateArn".to_string());
+    }
+    let declared_parameters = validate_template(
+        &stack_template,
+        &template_validation::SITE_OUTPUTS,
+        &supplied_parameters,
+        &clients.cfn,
+    )
+    .await?;
+
+    info!(name = &stack_name, "Using Cloudformation stack");
+    let stack_exists = prepare_stack(&stack_name, args, &clients.cfn, &clients.s3).await?;
+
+    // Check anything we're adopting will fit the template before changing anything
+    let adoption = match (&args.adopt_bucket, &args.adopt_distribution) {
+        (Some(bucket), Some(distribution_id)) => {
+            if stack_exists {
+                return Err(Error::AdoptIntoExistingStack {
+                    stack_name: stack_name.clone(),
+                });
+            }
+            info!(
+                bucket = bucket,
+                distribution_id = distribution_id,
+                "Checking the resources to adopt"
+            );
+            let bucket = s3_helpers::existing_bucket(bucket, &clients.s3).await?;
+            let distribution =
+                cloudfront_helpers::existing_distribution(distribution_id, &clients.cloudfront)
+                    .await?;
+            let names: Vec<String> = site_names.iter().map(|(name, _)| name.clone()).collect();
+            let mut warnings: Vec<String> = vec![];
+            if let Err(e) = adoption::check_compatible(
+                &bucket,
+                &distribution,
+                &names,
+                &clients.bucket_region,
+                &mut warnings,
+            ) {
+                if let Error::IncompatibleResources { problems } = &e {
+                    for problem in problems {
+                        error!(problem = problem, "Can't adopt");
+                    }
+                }
+                return Err(e);
+            }
+            for warning in &warnings {
+                warn!(change = warning, "Adopting will change the site");
+            }
+            Some((bucket, distribution))
+        }
+        _ => None,
+    };
+
+    // Make sure nothing else is using the names we're about to create records for. Records we've
+    // created on a previous deploy will point at our own distribution, and are fine.
+    let existing_endpoint = if stack_exists {
+        Some(site_endpoint(&stack_name, &clients.cfn, &clients.cloudfront).await?)
+    } else {
+        None
+    };
+
+    // CAA records belong to whichever stack holds the certificate. Those it already manages are
+    // its own; any other would stop it creating one.
+    let (caa_stack_name, caa_cfn) = if separate_certificate {
+        (&stack_names.certificate, &clients.certificate_cfn)
+    } else {
+        (&stack_name, &clients.cfn)
+    };
+    let mut conflicts: Vec<route53_helpers::ConflictingRecord> = vec![];
+    for (index, (name, name_zone_id)) in site_names.iter().enumerate() {
+        if let Some(name_zone_id) = name_zone_id {
+            let check_caa = args.site.caa
+                && cloudformation_helpers::stack_resource(
+                    caa_stack_name,
+                    &template::caa_record_logical_id((index > 0).then_some(name.as_str())),
+                    caa_cfn,
+                )
+                .await?
+                .is_none();
+            conflicts.extend(
+                route53_helpers::find_conflicting_records(
+                    name,
+                    name_zone_id,
+                    existing_endpoint.as_deref(),
+                    check_caa,
+                    &clients.r53,
+                )
+                .await?,
+            );
+        }
+    }
+
+    // Records pointing at a distribution we're adopting can't be imported along with it, so the
+    // stack creates them afresh once the old ones are out of the way
+    let mut adopted_records: Vec<route53_helpers::ConflictingRecord> = vec![];
+    if let Some((_, distribution)) = &adoption {
+        (adopted_records, conflicts) = conflicts
+            .into_iter()
+            .partition(|conflict| conflict.points_at(&distribution.domain_name));
+    }
+    if !adopted_records.is_empty() {
+        for record in &adopted_records {
+            info!(
+                name = record.record.name(),
+                r#type = record.record.r#type().as_str(),
+                target = record.target(),
+                "DNS record will be recreated by the stack"
+            );
+        }
+        warn!("The site's names won't resolve between the import and the stack recreating their records");
+        let question = format!(
+            "Delete and recreate {} DNS record(s)?",
+            adopted_records.len()
+        );
+        if !args.yes && !prompt_helpers::confirm(&question)? {
+            return Err(Error::ChangesDeclined);
+        }
+    }
+
+    for conflict in &conflicts {
+        warn!(
+            name = conflict.record.name(),
+            r#type = conflict.record.r#type().as_str(),
+            target = conflict.target(),
+            "Existing DNS record conflicts with the site"
+        );
+    }
+    if !conflicts.is_empty() {
+        if !args.takeover {
+            return Err(Error::ConflictingRecords {
+                count: conflicts.len(),
+            });
+        }
+        warn!(
+            "Taking over conflicting records; the names won't resolve until the stack has deployed"
+        );
+    }
+
+    // Each stack's records are only taken over once it's about to deploy, and put back if it
+    // doesn't. With a separate certificate stack, that's where the CAA records go.
+    let (certificate_conflicts, mut site_conflicts): (Vec<_>, Vec<_>) =
+        conflicts.into_iter().partition(|conflict| {
+            separate_certificate && conflict.record.r#type() == &aws_sdk_route53::types::RrType::Caa
+        });
+    site_conflicts.extend(adopted_records);
+
+    // CloudFront only accepts certificates from the control region, so if the site lives
+    // elsewhere its certificate gets a stack of its own there
+    let certificate_arn = if let Some(certificate_template) = &certificate_template {
+        let certificate_stack_name = stack_names.certificate.clone();
+        info!(
+            name = &certificate_stack_name,
+            "Using Cloudformation stack for certificate"
+        );
+        let certificate_stack_exists = prepare_stack(
+            &certificate_stack_name,
+            args,
+            &clients.certificate_cfn,
+            &clients.s3,
+        )
+        .await?;
+        replacing_records(
+            &certificate_conflicts,
+            &clients.r53,
+            deploy_stack(
+                &certificate_stack_name,
+                certificate_stack_exists,
+                certificate_template,
+                cloudformation_helpers::stack_parameters(zone_id.as_deref(), &fqdn, None),
+                stack_tags.clone(),
+                args,
+                &clients.certificate_cfn,
+            ),
+        )
+        .await?;
+        Some(
+            cloudformation_helpers::get_stack_output(
+                &certificate_stack_name,
+                &clients.certificate_cfn,
+                "CertificateArn",
+            )
+            .await?,
+        )
+    } else {
+        None
+    };
+
+    let stack_exists = match &adoption {
+        Some((bucket, distribution)) => {
+            adopt_resources(
+                &stack_name,
+                bucket,
+                distribution,
+                stack_tags.clone(),
+                args,
+                &clients.cfn,
+            )
+            .await?;
+            true
+        }
+        None => stack_exists,
+    };
+
+    // A template passed with --template needn't take all of our parameters
+    let mut parameters = cloudformation_helpers::stack_parameters(
+        zone_id.as_deref(),
+        &fqdn,
+        certificate_arn.as_deref(),
+    );
+    parameters.retain(|parameter| {
+        declared_parameters
+            .iter()
+            .any(|declared| Some(declared.as_str()) == parameter.parameter_key())
+    });
+    replacing_records(
+        &site_conflicts,
+        &clients.r53,
+        deploy_stack(
+            &stack_name,
+            stack_exists,
+            &stack_template,
+            parameters,
+            stack_tags,
+            args,
+            &clients.cfn,
+        ),
+    )
+    .await?;
+
+    // Upload the site
+    info!("Finding website bucket");
+    let bucket_name =
+        cloudformation_helpers::get_stack_output(&stack_name, &clients.cfn, "StaticWebsiteBucket")
+            .await?;
+    info!(bucket = &bucket_name, "Uploading");
+    let uploaded_keys = upload_directory(path, &bucket_name, &clients.s3).await?;
+
+    // Invalidate the distribution
+    let distribution_id =
+        cloudformation_helpers::get_stack_output(&stack_name, &clients.cfn, "Distribution").await?;
+    invalidate_and_wait(
+        &distribution_id,
+        &["/*".to_string()],
+        args.invalidation_timeout,
+        args.wait.backoff(),
+        &clients.cloudfront,
+    )
+    .await?;
+
+    info!("Distribution invalidated. Ready to go!");
+    let endpoint = site_endpoint(&stack_name, &clients.cfn, &clients.cloudfront).await?;
+    if args.site.external_dns {
+        for (name, _) in &site_names {
+            info!(
+                name = name,
+                r#type = "CNAME",
+                value = &endpoint,
+                "Create this DNS record to point the site at the distribution"
+            );
+        }
+        if fqdn == *domain_zone {
+            warn!("Most DNS providers can't CNAME the apex of a zone; use your provider's ALIAS, ANAME or CNAME flattening support instead");
+        }
+    }
+
+    // Check everything is actually reachable. With DNS hosted elsewhere the records may not
+    // exist yet, so there's nothing we can usefully check.
+    if args.skip_verify {
+        info!("Skipping verification");
+    } else if args.site.external_dns {
+        info!("Skipping verification; create the DNS records above, then visit the site");
+    } else {
+        info!("Verifying site");
+        let names: Vec<String> = site_names.iter().map(|(name, _)| name.clone()).collect();
+        verify_helpers::verify_site(
+            &names,
+            &endpoint,
+            &uploaded_keys,
+            Duration::from_secs(60 * args.verify_timeout),
+        )
+        .await?;
+    }
+
+    for (name, _) in &site_names {
+        info!(href = format!("https://{}", name), "Link");
+    }
+    Ok(())
+}
+
+///
+/// Gets a stack ready to deploy, returning whether it exists. A stack part way through an
+/// operation is waited out. A stack that can only be fixed by deleting it - such as one whose
+/// first deploy failed and was rolled back - is deleted so it can be recreated, with --recover
+/// or once the user has agreed to it.
+///
+async fn prepare_stack(
+    stack_name: &str,
+    args: &DeployArgs,
+    cfn_client: &aws_sdk_cloudformation::Client,
+    s3_client: &aws_sdk_s3::Client,
+) -> Result<bool, Error> {
+    let state = wait_on_stack(
+        cloudformation_helpers::wait_for_stack_to_settle(
+            stack_name,
+            cfn_client,
+            args.wait.backoff(),
+        ),
+        args.wait.stack_timeout(),
+        stack_name,
+        cfn_client,
+        "Something else is still working on the stack; re-run once it's finished, or with a longer --stack-timeout",
+    )
+    .await?;
+
+    match state {
+        StackState::Missing => Ok(false),
+        StackState::Ready => Ok(true),
+        StackState::Dead(status) => {
+            warn!(
+                name = stack_name,
+                status = status.as_str(),
+                "Stack can only be recovered by deleting and recreating it"
+            );
+            let question = format!("Delete {} and recreate it?", stack_name);
+            if !args.recover && !prompt_helpers::confirm(&question)? {
+                return Err(Error::DeadStack {
+                    stack_name: stack_name.to_string(),
+                    status: status.as_str().to_string(),
+                });
+            }
+
+            let stack_id = cloudformation_helpers::find_stack_id(stack_name, cfn_client)
+                .await?
+                .ok_or(Error::StackNotFound {
+                    stack_name: stack_name.to_string(),
+                })?;
+            // A stack whose first deploy rolled back has nothing worth protecting, but would keep
+            // its termination protection if it was deployed with --protect
+            if cloudformation_helpers::termination_protected(&stack_id, cfn_client).await? {
+                info!(name = stack_name, "Removing termination protection");
+                cloudformation_helpers::set_termination_protection(&stack_id, false, cfn_client)
+                    .await?;
+            }
+            delete_stack_and_wait(stack_name, &stack_id, cfn_client, s3_client, &args.wait).await?;
+            Ok(false)
+        }
+        StackState::Stuck(status) | StackState::InProgress(status) => {
+            if status == StackStatus::DeleteFailed {
+                warn!(
+                    name = stack_name,
+                    "The stack's deletion failed part way; run destroy again to finish it off, or fix it in the CloudFormation console"
+                );
+            }
+            error!(
+                stack_status = status.as_str(),
+                "Got status and can't proceed"
+            );
+            Err(Error::UnusableStackStatus {
+                stack_name: stack_name.to_string(),
+                status: status.as_str().to_string(),
+            })
+        }
+    }
+}
+
+///
+/// Creates the site's stack by importing an existing bucket and distribution into it. The site's
+/// own template is applied afterwards, as an update to the stack.
+///
+async fn adopt_resources(
+    stack_name: &str,
+    bucket: &adoption::ExistingBucket,
+    distribution: &adoption::ExistingDistribution,
+    tags: Vec<Tag>,
+    args: &DeployArgs,
+    cfn_client: &aws_sdk_cloudformation::Client,
+) -> Result<(), Error> {
+    info!(
+        name = stack_name,
+        bucket = &bucket.name,
+        distribution_id = &distribution.id,
+        "Importing existing resources into a new stack"
+    );
+    let change_set_id = timeout(
+        Duration::from_secs(60 * 5),
+        cloudformation_helpers::create_import_change_set(
+            stack_name,
+            cfn_client,
+            &adoption::render_import_template(bucket, distribution).to_yaml(),
+            adoption::resources_to_import(bucket, distribution),
+            tags,
+            args.wait.backoff(),
+        ),
+    )
+    .await??;
+    cloudformation_helpers::execute_change_set(&change_set_id, cfn_client).await?;
+
+    wait_on_stack(
+        cloudformation_helpers::wait_for_stack(stack_name, cfn_client, args.wait.backoff()),
+        args.wait.stack_timeout(),
+        stack_name,
+        cfn_client,
+        "The import carries on in CloudFormation; once it's finished, re-run without --adopt-bucket and --adopt-distribution to finish the deploy",
+    )
+    .await?;
+    info!(name = stack_name, "Resources imported");
+    Ok(())
+}
+
+///
+/// Checks a template over locally, then has CloudFormation validate it, returning the parameters
+/// it declares. Any problems are logged before the error is returned.
+///
+async fn validate_template(
+    template_body: &str,
+    required_outputs: &[&str],
+    supplied_parameters: &[String],
+    cfn_client: &aws_sdk_cloudformation::Client,
+) -> Result<Vec<String>, Error> {
+    info!("Validating template");
+    let validated = match template_validation::check_template(
+        template_body,
+        required_outputs,
+        supplied_parameters,
+    ) {
+        Ok(declared_parameters) => {
+            cloudformation_helpers::validate_template(template_body, cfn_client)
+                .await
+                .map(|_| declared_parameters)
+        }
+        Err(e) => Err(e),
+    };
+
+    if let Err(Error::InvalidTemplate { problems }) = &validated {
+        for problem in problems {
+            error!(problem = problem, "Template problem");
+        }
+    }
+    validated
+}
+
+///
+/// Deletes the records in the way of those a stack is about to create, then deploys it with
+/// `deploy`. Records pointing at an adopted distribution, or taken over with --takeover, are put
+/// back if the stack doesn't deploy - the changes are declined, say, or it rolls back - so the
+/// names don't go dark.
+///
+async fn replacing_records(
+    records: &[route53_helpers::ConflictingRecord],
+    r53_client: &aws_sdk_route53::Client,
+    deploy: impl Future<Output = Result<(), Error>>,
+) -> Result<(), Error> {
+    let mut deployed = route53_helpers::delete_records(records, r53_client).await;
+    if deployed.is_ok() {
+        deployed = deploy.await;
+    }
+    if deployed.is_err() && !records.is_empty() {
+        warn!("The stack didn't deploy; restoring the DNS records it was to replace");
+        route53_helpers::restore_records(records, r53_client).await;
+    }
+    deployed
+}
+
+///
+/// Creates the stack if it doesn't exist yet, or updates it if it does, then waits for the deploy
+/// to finish. Updates go through a change set, so the changes can be reviewed first; if any
+/// resources would be replaced, we ask before going ahead. With DNS hosted outside Route53, the
+/// records needed to validate the certificate are logged while we wait for it to be issued.
+///
+async fn deploy_stack(
+    stack_name: &str,
+    stack_exists: bool,
+    stack_template: &str,
+    parameters: Vec<Parameter>,
+    tags: Vec<Tag>,
+    args: &DeployArgs,
+    cfn_client: &aws_sdk_cloudformation::Client,
+) -> Result<(), Error> {
+    if !stack_exists {
+        info!(name = stack_name, "Stack doesn't exist; creating");
+        let stack_id = cloudformation_helpers::create_stack(
+            stack_name,
+            cfn_client,
+            stack_template,
+            parameters,
+            tags,
+            args.protection().unwrap_or(false),
+        )
+        .await?;
+        info!(stack_id = &stack_id, "Stack created");
+    } else {
+        // Protection goes on, or comes off, before the update so the update is held to it
+        let protected = match args.protection() {
+            Some(protect) => {
+                if protect
+                    != cloudformation_helpers::termination_protected(stack_name, cfn_client).await?
+                {
+                    info!(
+                        name = stack_name,
+                        protect = protect,
+                        "Updating stack protection"
+                    );
+                    cloudformation_helpers::set_protection(stack_name, protect, cfn_client).await?;
+                }
+                protect
+            }
+            None => cloudformation_helpers::termination_protected(stack_name, cfn_client).await?,
+        };
+
+        info!(
+            name = stack_name,
+            "Stack exists; working out what will change"
+        );
+        let change_set_id = match timeout(
+            Duration::from_secs(60 * 5),
+            cloudformation_helpers::create_change_set(
+                stack_name,
+                cfn_client,
+                stack_template,
+                parameters,
+                Some(tags),
+                args.wait.backoff(),
+            ),
+        )
+        .await??
+        {
+            Some(change_set_id) => change_set_id,
+            None => {
+                info!(name = stack_name, "Stack is already up to date");
+                return Ok(());
+            }
+        };
+
+        review_changes(stack_name, &change_set_id, protected, args.yes, cfn_client).await?;
+
+        info!(name = stack_name, "Updating stack");
+        cloudformation_helpers::execute_change_set(&change_set_id, cfn_client).await?;
+    }
+
+    if args.site.external_dns && stack_template.contains("AWS::CertificateManager::Certificate") {
+        info!("Waiting for the certificate to be validated");
+        wait_on_stack(
+            cloudformation_helpers::wait_for_certificate_validation(
+                stack_name,
+                cfn_client,
+                args.wait.backoff(),
+            ),
+            Duration::from_secs(60 * args.validation_timeout),
+            stack_name,
+            cfn_client,
+            "Create the DNS records logged above, then re-run the same command - perhaps with a longer --validation-timeout - to carry on once the certificate is issued",
+        )
+        .await?;
+    }
+
+    info!("Waiting for stack deployment to complete");
+    wait_on_stack(
+        cloudformation_helpers::wait_for_stack(stack_name, cfn_client, args.wait.backoff()),
+        args.wait.stack_timeout(),
+        stack_name,
+        cfn_client,
+        "The deploy carries on in CloudFormation; re-run the same command - perhaps with a longer --stack-timeout - to wait for it and pick up where it left off",
+    )
+    .await?;
+    info!("Stack deploy complete");
+    Ok(())
+}
+
+///
+/// The domain name of the site's distribution, which DNS records point at. Templates deployed
+/// with --template needn't output it, in which case we ask CloudFront.
+///
+async fn site_endpoint(
+    stack_name: &str,
+    cfn_client: &aws_sdk_cloudformation::Client,
+    cloudfront_client: &aws_sdk_cloudfront::Client,
+) -> Result<String, Error> {
+    match cloudformation_helpers::get_stack_output(
+        stack_name,
+        cfn_client,
+        "StaticWebsiteCfnEndpoint",
+    )
+    .await
+    {
+        Err(Error::StackOutputNotFound) => {
+            let distribution_id =
+                cloudformation_helpers::get_stack_output(stack_name, cfn_client, "Distribution")
+                    .await?;
+            Ok(
+                cloudfront_helpers::distribution_domain_name(&distribution_id, cloudfront_client)
+                    .await?,
+            )
+        }
+        result => result,
+    }
+}
@@ -0,0 +1,131 @@
+use super::{delete_stack_and_wait, find_stack_names};
+use crate::clients::Clients;
+use crate::cloudformation_helpers;
+use crate::error::Error;
+use crate::{prompt_helpers, DestroyArgs};
+use tracing::{info, warn};
+
+///
+/// Tears down a deployed site, along with everything uploaded to it.
+///
+pub async fn destroy(args: &DestroyArgs) -> Result<(), Error> {
+    let clients = Clients::new(
+        args.site.partition,
+        &args.site.bucket_region,
+        args.site.profile.as_deref(),
+    )
+    .await?;
+
+    let fqdn = args.site.fqdn()?;
+    let separate_certificate = clients.separate_certificate();
+    let stack_names = find_stack_names(
+        &fqdn,
+        args.site.stack_name.as_deref(),
+        &clients.cfn,
+        separate_certificate.then_some(&clients.certificate_cfn),
+    )
+    .await?;
+    let stack_name = stack_names.site.clone();
+    let stack_id = cloudformation_helpers::find_stack_id(&stack_name, &clients.cfn)
+        .await?
+        .ok_or(Error::StackNotFound {
+            stack_name: stack_name.clone(),
+        })?;
+    info!(name = &stack_name, "Found Cloudformation stack");
+
+    // A site whose bucket lives outside the control region has its certificate in a stack of its
+    // own, which can only go once the distribution using it has
+    let certificate_stack_id = if separate_certificate {
+        cloudformation_helpers::find_stack_id(&stack_names.certificate, &clients.certificate_cfn)
+            .await?
+    } else {
+        None
+    };
+
+    // Check the stacks can be deleted up front, rather than leaving the site half destroyed
+    let unprotect = needs_unprotecting(&stack_name, args.unprotect, &clients.cfn).await?;
+    let unprotect_certificate = match certificate_stack_id {
+        Some(_) => {
+            needs_unprotecting(
+                &stack_names.certificate,
+                args.unprotect,
+                &clients.certificate_cfn,
+            )
+            .await?
+        }
+        None => false,
+    };
+
+    let confirmed = match &args.confirm {
+        Some(confirmation) => *confirmation == fqdn,
+        None => prompt_helpers::confirm_by_typing(
+            &format!(
+                "This permanently deletes {} and everything uploaded to it. Type the domain to confirm:",
+                fqdn
+            ),
+            &fqdn,
+        )?,
+    };
+    if !confirmed {
+        return Err(Error::DestroyNotConfirmed);
+    }
+
+    if unprotect {
+        warn!(name = &stack_name, "Removing the stack's protection");
+        cloudformation_helpers::set_protection(&stack_name, false, &clients.cfn).await?;
+    }
+    delete_stack_and_wait(
+        &stack_name,
+        &stack_id,
+        &clients.cfn,
+        &clients.s3,
+        &args.wait,
+    )
+    .await?;
+
+    if let Some(certificate_stack_id) = certificate_stack_id {
+        let certificate_stack_name = stack_names.certificate;
+        if unprotect_certificate {
+            warn!(
+                name = &certificate_stack_name,
+                "Removing the stack's protection"
+            );
+            cloudformation_helpers::set_protection(
+                &certificate_stack_name,
+                false,
+                &clients.certificate_cfn,
+            )
+            .await?;
+        }
+        delete_stack_and_wait(
+            &certificate_stack_name,
+            &certificate_stack_id,
+            &clients.certificate_cfn,
+            &clients.s3,
+            &args.wait,
+        )
+        .await?;
+    }
+
+    info!(domain = &fqdn, "Site destroyed");
+    Ok(())
+}
+
+///
+/// Checks whether a stack's protection has to come off before it can be deleted. CloudFormation
+/// refuses to delete a stack with termination protection, so unless we've been told to remove it,
+/// the deletion is stopped before it starts.
+///
+async fn needs_unprotecting(
+    stack_name: &str,
+    unprotect: bool,
+    cfn_client: &aws_sdk_cloudformation::Client,
+) -> Result<bool, Error> {
+    let protected = cloudformation_helpers::termination_protected(stack_name, cfn_client).await?;
+    if protected && !unprotect {
+        return Err(Error::StackProtected {
+            stack_name: stack_name.to_string(),
+        });
+    }
+    Ok(protected)
+}
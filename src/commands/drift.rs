@@ -0,0 +1,180 @@
+use super::{find_stack_names, review_changes, wait_on_stack};
+use crate::clients::Clients;
+use crate::cloudformation_helpers;
+use crate::error::Error;
+use crate::template;
+use crate::{prompt_helpers, DriftArgs};
+use aws_sdk_cloudformation::types::StackResourceDriftStatus;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::time::timeout;
+use tracing::{info, warn};
+
+///
+/// Checks the site's stacks for drift - changes made to their resources outside CloudFormation -
+/// and describes any they find. CloudFormation won't touch a resource whose template hasn't
+/// changed, so to put drifted resources back we redeploy the stack's template with a harmless
+/// change stamped on each of them, then take the stamp back out again.
+///
+pub async fn drift(args: &DriftArgs) -> Result<(), Error> {
+    let clients = Clients::new(
+        args.site.partition,
+        &args.site.bucket_region,
+        args.site.profile.as_deref(),
+    )
+    .await?;
+    let separate_certificate = clients.separate_certificate();
+
+    let fqdn = args.site.fqdn()?;
+    let stack_names = find_stack_names(
+        &fqdn,
+        args.site.stack_name.as_deref(),
+        &clients.cfn,
+        separate_certificate.then_some(&clients.certificate_cfn),
+    )
+    .await?;
+    if cloudformation_helpers::find_stack_id(&stack_names.site, &clients.cfn)
+        .await?
+        .is_none()
+    {
+        return Err(Error::StackNotFound {
+            stack_name: stack_names.site,
+        });
+    }
+
+    drift_stack(&stack_names.site, args, &clients.cfn).await?;
+    if separate_certificate
+        && cloudformation_helpers::find_stack_id(&stack_names.certificate, &clients.certificate_cfn)
+            .await?
+            .is_some()
+    {
+        drift_stack(&stack_names.certificate, args, &clients.certificate_cfn).await?;
+    }
+    Ok(())
+}
+
+///
+/// Checks one of the site's stacks for drift, offering to re-apply its template to any resources
+/// that have drifted. The marker that makes CloudFormation update them stays in the stack's
+/// template until the next deploy.
+///
+async fn drift_stack(
+    stack_name: &str,
+    args: &DriftArgs,
+    cfn_client: &aws_sdk_cloudformation::Client,
+) -> Result<(), Error> {
+    info!(name = stack_name, "Detecting drift");
+    let drifts = timeout(
+        args.wait.stack_timeout(),
+        cloudformation_helpers::detect_drift(stack_name, cfn_client, args.wait.backoff()),
+    )
+    .await??;
+    if drifts.is_empty() {
+        info!(
+            name = stack_name,
+            "Stack matches its template; nothing has drifted"
+        );
+        return Ok(());
+    }
+
+    let mut modified: Vec<String> = vec![];
+    for drift in &drifts {
+        let resource = drift.logical_resource_id().unwrap_or_default();
+        let status = drift.stack_resource_drift_status();
+        warn!(
+            resource = resource,
+            resource_type = drift.resource_type().unwrap_or_default(),
+            status = status.map(|s| s.as_str()).unwrap_or_default(),
+            "Resource has drifted"
+        );
+        for difference in drift.property_differences() {
+            info!(
+                resource = resource,
+                property = difference.property_path().unwrap_or_default(),
+                difference = difference
+                    .difference_type()
+                    .map(|d| d.as_str())
+                    .unwrap_or_default(),
+                expected = difference.expected_value().unwrap_or_default(),
+                actual = difference.actual_value().unwrap_or_default(),
+                "Difference"
+            );
+        }
+
+        if status == Some(&StackResourceDriftStatus::Deleted) {
+            warn!(
+                resource = resource,
+                "Resource was deleted; CloudFormation can't update it back into existence, so it needs recreating by hand"
+            );
+        } else {
+            modified.push(resource.to_string());
+        }
+    }
+    if modified.is_empty() {
+        return Ok(());
+    }
+
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("the clock should be after the epoch")
+        .as_secs()
+        .to_string();
+    let template_body = cloudformation_helpers::get_template(stack_name, cfn_client).await?;
+    let (stamped_template, unrepairable) =
+        template::stamp_for_repair(&template_body, &modified, &stamp)?;
+    for resource in &unrepairable {
+        warn!(
+            resource = resource,
+            "Updating this resource wouldn't re-apply all of its settings; it needs fixing by hand"
+        );
+    }
+    if unrepairable.len() == modified.len() {
+        return Ok(());
+    }
+
+    let parameters = cloudformation_helpers::previous_parameters(stack_name, cfn_client).await?;
+    let change_set_id = timeout(
+        args.wait.stack_timeout(),
+        cloudformation_helpers::create_change_set(
+            stack_name,
+            cfn_client,
+            &stamped_template,
+            parameters,
+            None,
+            args.wait.backoff(),
+        ),
+    )
+    .await??;
+    let Some(change_set_id) = change_set_id else {
+        info!(name = stack_name, "Nothing to re-apply");
+        return Ok(());
+    };
+
+    // A repair is held to the same checks as a deploy
+    let protected = cloudformation_helpers::termination_protected(stack_name, cfn_client).await?;
+    review_changes(stack_name, &change_set_id, protected, args.yes, cfn_client).await?;
+    let question = format!(
+        "Re-apply the template to {} drifted resource(s)?",
+        modified.len() - unrepairable.len()
+    );
+    if !args.repair && !prompt_helpers::confirm(&question)? {
+        cloudformation_helpers::delete_change_set(&change_set_id, cfn_client).await?;
+        info!("Leaving the stack as it is");
+        return Ok(());
+    }
+
+    info!(name = stack_name, "Re-applying template");
+    cloudformation_helpers::execute_change_set(&change_set_id, cfn_client).await?;
+    wait_on_stack(
+        cloudformation_helpers::wait_for_stack(stack_name, cfn_client, args.wait.backoff()),
+        args.wait.stack_timeout(),
+        stack_name,
+        cfn_client,
+        "The repair carries on in CloudFormation; run drift again once it's finished to check the result",
+    )
+    .await?;
+    info!(
+        name = stack_name,
+        "Template re-applied; run drift again to check the result"
+    );
+    Ok(())
+}
@@ -0,0 +1,43 @@
+use super::{find_stack_names, invalidate_and_wait};
+use crate::clients::Clients;
+use crate::cloudformation_helpers;
+use crate::cloudfront_helpers;
+use crate::error::Error;
+use crate::InvalidateArgs;
+use tracing::info;
+
+///
+/// Has CloudFront fetch the given paths of a deployed site afresh.
+///
+pub async fn invalidate(args: &InvalidateArgs) -> Result<(), Error> {
+    let paths = cloudfront_helpers::invalidation_paths(&args.paths)?;
+    let clients = Clients::new(
+        args.site.partition,
+        &args.site.bucket_region,
+        args.site.profile.as_deref(),
+    )
+    .await?;
+    let fqdn = args.site.fqdn()?;
+    let stack_name = find_stack_names(&fqdn, args.site.stack_name.as_deref(), &clients.cfn, None)
+        .await?
+        .site;
+    if cloudformation_helpers::find_stack_id(&stack_name, &clients.cfn)
+        .await?
+        .is_none()
+    {
+        return Err(Error::StackNotFound { stack_name });
+    }
+
+    let distribution_id =
+        cloudformation_helpers::get_stack_output(&stack_name, &clients.cfn, "Distribution").await?;
+    invalidate_and_wait(
+        &distribution_id,
+        &paths,
+        args.invalidation_timeout,
+        args.wait.backoff(),
+        &clients.cloudfront,
+    )
+    .await?;
+    info!("Distribution invalidated");
+    Ok(())
+}
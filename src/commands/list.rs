@@ -0,0 +1,32 @@
+use crate::cloudformation_helpers;
+use crate::error::Error;
+use crate::{clients, ListArgs};
+use tracing::info;
+
+///
+/// Lists the sites deployed to the account, in each of the regions asked for.
+///
+pub async fn list(args: &ListArgs) -> Result<(), Error> {
+    let regions = args.regions();
+    let mut found = 0;
+    for region in &regions {
+        args.partition.validate(region)?;
+        let cfn_client = aws_sdk_cloudformation::Client::new(
+            &clients::load_config(region.clone(), args.profile.as_deref()).await,
+        );
+        for stack in cloudformation_helpers::site_stacks(&cfn_client).await? {
+            info!(
+                domain = stack.domain_name.as_deref().unwrap_or_default(),
+                name = &stack.name,
+                region = region,
+                status = stack.status.as_str(),
+                last_deployed = stack.last_deployed.as_deref().unwrap_or_default(),
+                "Site"
+            );
+            found += 1;
+        }
+    }
+
+    info!(count = found, regions = regions.join(", "), "Found sites");
+    Ok(())
+}
@@ -0,0 +1,445 @@
+mod config;
+mod deploy;
+mod destroy;
+mod drift;
+mod invalidate;
+mod list;
+mod preview;
+mod status;
+mod synth;
+
+pub use self::config::validate_config;
+pub use self::deploy::deploy;
+pub use self::destroy::destroy;
+pub use self::drift::drift;
+pub use self::invalidate::invalidate;
+pub use self::list::list;
+pub use self::preview::{preview, preview_gc};
+pub use self::status::status;
+pub use self::synth::synth;
+
+use crate::cloudformation_helpers;
+use crate::cloudfront_helpers::{invalidate_distribution, wait_for_invalidation};
+use crate::error::Error;
+use crate::polling::Backoff;
+use crate::stack_names::StackNames;
+use crate::template::{self, CaaPolicy, LoggingConfig, ResponseHeaders, SiteAlias, SiteConfig};
+use crate::{prompt_helpers, route53_helpers, s3_helpers, SiteConfigArgs, WaitArgs};
+use aws_sdk_cloudformation::types::{ChangeAction, Replacement, ResourceChange};
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::timeout;
+use tracing::{info, warn};
+
+///
+/// A site ready to render the template for, with the zone each of its names lives in looked up.
+///
+struct ResolvedSite {
+    fqdn: String,
+    zone_id: Option<String>,
+    config: SiteConfig,
+}
+
+impl ResolvedSite {
+    ///
+    /// Each of the site's names, along with the zone its records go in.
+    ///
+    fn names(&self) -> Vec<(String, Option<String>)> {
+        std::iter::once((self.fqdn.clone(), self.zone_id.clone()))
+            .chain(
+                self.config
+                    .aliases
+                    .iter()
+                    .map(|a| (a.name.clone(), a.zone_id.clone())),
+            )
+            .collect()
+    }
+}
+
+///
+/// Looks up the zones for the site's domain and aliases, unless DNS is hosted elsewhere, and puts
+/// together the configuration its template is rendered from.
+///
+async fn resolve_site(
+    args: &SiteConfigArgs,
+    separate_certificate: bool,
+    r53_client: &aws_sdk_route53::Client,
+) -> Result<ResolvedSite, Error> {
+    let domain_zone = args.domain_zone()?;
+
+    // Try find the zone ID, unless DNS lives elsewhere
+    let zone_id = if args.external_dns {
+        info!("DNS is hosted outside Route53; skipping zone lookup");
+        None
+    } else {
+        let zone_id = match &args.zone_id {
+            Some(zone_id) => route53_helpers::verify_zone(zone_id, domain_zone, r53_client).await?,
+            None => route53_helpers::find_zone(domain_zone, r53_client).await?,
+        };
+        info!(zone = &zone_id, "Found zone");
+        Some(zone_id)
+    };
+
+    let fqdn = site_domain(&args.domain_name, domain_zone);
+
+    // Work out which zone each alias lives in
+    let mut known_zones: Vec<(String, String)> = zone_id
+        .iter()
+        .map(|zone_id| (domain_zone.to_string(), zone_id.clone()))
+        .collect();
+    let mut aliases: Vec<SiteAlias> = vec![];
+    for alias in &args.aliases {
+        let alias = alias.to_ascii_lowercase();
+        template::validate_alias(&alias, &fqdn)?;
+        let alias_zone_id = if args.external_dns {
+            None
+        } else {
+            let alias_zone_id =
+                route53_helpers::find_zone_for_name(&alias, &known_zones, r53_client).await?;
+            info!(
+                alias = &alias,
+                zone = &alias_zone_id,
+                "Found zone for alias"
+            );
+            known_zones.push((alias.clone(), alias_zone_id.clone()));
+            Some(alias_zone_id)
+        };
+        aliases.push(SiteAlias {
+            name: alias,
+            zone_id: alias_zone_id,
+        });
+    }
+
+    // These can come from the config file, so clap can't insist on --caa alongside them
+    if !args.caa && (!args.caa_issuers.is_empty() || args.caa_iodef.is_some()) {
+        return Err(Error::InvalidCaaPolicy {
+            reason: "--caa-issuer and --caa-iodef need --caa".to_string(),
+        });
+    }
+    let caa = args.caa.then(|| CaaPolicy {
+        issuers: args.caa_issuers.clone(),
+        iodef: args.caa_iodef.clone(),
+    });
+    let response_headers =
+        (args.security_headers || !args.headers.is_empty()).then(|| ResponseHeaders {
+            security_headers: args.security_headers,
+            custom: args.headers.clone(),
+        });
+
+    let config = SiteConfig {
+        aliases,
+        redirect_to_canonical: args.redirect_to_canonical,
+        external_dns: args.external_dns,
+        caa,
+        separate_certificate,
+        cache_behaviors: args.cache_behaviors.clone(),
+        error_pages: args.error_pages.clone(),
+        redirect_to_https: args.redirect_to_https,
+        response_headers,
+        logging: LoggingConfig {
+            enabled: !args.no_logging,
+            retention_days: args.log_retention_days,
+        },
+        web_acl_arn: args.web_acl_arn.clone(),
+        bucket_name: None,
+    };
+
+    Ok(ResolvedSite {
+        fqdn,
+        zone_id,
+        config,
+    })
+}
+
+///
+/// Finds the name of the website bucket in a deployed stack, if it's one we adopted.
+///
+async fn deployed_bucket_name(
+    stack_name: &str,
+    cfn_client: &aws_sdk_cloudformation::Client,
+) -> Result<Option<String>, Error> {
+    if cloudformation_helpers::find_stack_id(stack_name, cfn_client)
+        .await?
+        .is_none()
+    {
+        return Ok(None);
+    }
+    let template_body = cloudformation_helpers::get_template(stack_name, cfn_client).await?;
+    template::adopted_bucket_name(&template_body)
+}
+
+///
+/// Logs one of the changes a change set would make.
+///
+fn log_planned_change(change: &ResourceChange) {
+    info!(
+        action = change.action().map(|a| a.as_str()).unwrap_or_default(),
+        resource = change.logical_resource_id().unwrap_or_default(),
+        resource_type = change.resource_type().unwrap_or_default(),
+        replacement = change.replacement().map(|r| r.as_str()).unwrap_or("False"),
+        "Planned change"
+    );
+}
+
+///
+/// Logs the changes a change set would make, and checks we can go ahead with them: a protected
+/// stack mustn't have its protected resources replaced or removed, and replacing anything needs
+/// confirming unless `yes` is given. If we can't go ahead, the change set is deleted. Returns the
+/// number of changes.
+///
+async fn review_changes(
+    stack_name: &str,
+    change_set_id: &str,
+    protected: bool,
+    yes: bool,
+    cfn_client: &aws_sdk_cloudformation::Client,
+) -> Result<usize, Error> {
+    let changes = cloudformation_helpers::change_set_changes(change_set_id, cfn_client).await?;
+    let mut replacements = 0;
+    let mut protected_changes: Vec<&str> = vec![];
+    for change in &changes {
+        log_planned_change(change);
+        if matches!(
+            change.replacement(),
+            Some(Replacement::True | Replacement::Conditional)
+        ) {
+            replacements += 1;
+        }
+
+        // The stack policy would only stop these part way through the update, which would then
+        // roll back
+        let resource = change.logical_resource_id().unwrap_or_default();
+        if protected
+            && template::PROTECTED_RESOURCES.contains(&resource)
+            && (change.replacement() == Some(&Replacement::True)
+                || change.action() == Some(&ChangeAction::Remove))
+        {
+            protected_changes.push(resource);
+        }
+    }
+
+    if !protected_changes.is_empty() {
+        cloudformation_helpers::delete_change_set(change_set_id, cfn_client).await?;
+        return Err(Error::ProtectedResourceChange {
+            stack_name: stack_name.to_string(),
+            resources: protected_changes.join(", "),
+        });
+    }
+
+    if replacements > 0 {
+        warn!(
+            count = replacements,
+            "Some resources will be replaced. A replaced certificate or distribution can leave the site unavailable while it's recreated"
+        );
+        let question = format!("Replace {} resource(s)?", replacements);
+        if !yes && !prompt_helpers::confirm(&question)? {
+            cloudformation_helpers::delete_change_set(change_set_id, cfn_client).await?;
+            return Err(Error::ChangesDeclined);
+        }
+    }
+
+    Ok(changes.len())
+}
+
+///
+/// Invalidates the paths in the distribution, and waits for CloudFront to finish - for up to the
+/// timeout, in minutes.
+///
+async fn invalidate_and_wait(
+    distribution_id: &str,
+    paths: &[String],
+    invalidation_timeout: u64,
+    backoff: Backoff,
+    cloudfront_client: &aws_sdk_cloudfront::Client,
+) -> Result<(), Error> {
+    info!(
+        distribution_id = distribution_id,
+        paths = paths.join(", "),
+        "Invalidating distribution"
+    );
+    let invalidation_id =
+        invalidate_distribution(distribution_id, paths, cloudfront_client).await?;
+    let invalidated = timeout(
+        Duration::from_secs(60 * invalidation_timeout),
+        wait_for_invalidation(
+            &invalidation_id,
+            distribution_id,
+            cloudfront_client,
+            backoff,
+        ),
+    )
+    .await;
+    match invalidated {
+        Ok(result) => Ok(result?),
+        Err(_) => Err(Error::StillPending {
+            operation: "the CDN invalidation".to_string(),
+            pending: vec![format!(
+                "invalidation {} of distribution {}",
+                invalidation_id, distribution_id
+            )],
+            hint: "CloudFront carries on invalidating, so the site will update once it's done; use a longer --invalidation-timeout to wait for it".to_string(),
+        }),
+    }
+}
+
+///
+/// Waits for `wait` - one of the stack pollers - to finish within `limit`. If it doesn't, the
+/// error describes what the stack was still working on, and `hint` how to pick things up again.
+///
+async fn wait_on_stack<T>(
+    wait: impl Future<Output = Result<T, Error>>,
+    limit: Duration,
+    stack_name: &str,
+    cfn_client: &aws_sdk_cloudformation::Client,
+    hint: &str,
+) -> Result<T, Error> {
+    match timeout(limit, wait).await {
+        Ok(result) => result,
+        Err(_) => {
+            let mut pending =
+                cloudformation_helpers::pending_resources(stack_name, cfn_client).await?;
+            if pending.is_empty() {
+                pending.push("no resources reported as in progress".to_string());
+            }
+            Err(Error::StillPending {
+                operation: format!("stack {}", stack_name),
+                pending,
+                hint: hint.to_string(),
+            })
+        }
+    }
+}
+
+///
+/// Deletes a stack and waits for it to go. The site's buckets are versioned, and CloudFormation
+/// won't delete a bucket that isn't empty, so every version of every object is deleted first.
+///
+async fn delete_stack_and_wait(
+    stack_name: &str,
+    stack_id: &str,
+    cfn_client: &aws_sdk_cloudformation::Client,
+    s3_client: &aws_sdk_s3::Client,
+    wait: &WaitArgs,
+) -> Result<(), Error> {
+    // The distribution can keep writing logs while CloudFormation deletes it, so if a bucket has
+    // filled up again by the time its turn comes, we empty it again and have another go
+    for attempt in 1..=DELETE_ATTEMPTS {
+        for logical_id in ["WebsiteBucket", "LoggingBucket"] {
+            if let Some(bucket) =
+                cloudformation_helpers::stack_resource_id(stack_id, logical_id, cfn_client).await?
+            {
+                info!(bucket = &bucket, "Emptying bucket");
+                s3_helpers::empty_bucket(&bucket, s3_client).await?;
+            }
+        }
+
+        info!(name = stack_name, "Deleting stack");
+        cloudformation_helpers::delete_stack(stack_id, cfn_client).await?;
+        let deleted = wait_on_stack(
+            cloudformation_helpers::wait_for_stack_deletion(stack_id, cfn_client, wait.backoff()),
+            wait.stack_timeout(),
+            stack_id,
+            cfn_client,
+            "The deletion carries on in CloudFormation; re-run the same command - perhaps with a longer --stack-timeout - to finish it off",
+        )
+        .await;
+        match deleted {
+            Ok(()) => break,
+            Err(err @ Error::StackFailed { .. }) if attempt < DELETE_ATTEMPTS => {
+                warn!(error = %err, "Stack deletion failed; emptying buckets and retrying");
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    info!(name = stack_name, "Stack deleted");
+    Ok(())
+}
+
+/// How many times we try to delete a stack before giving up
+const DELETE_ATTEMPTS: u32 = 3;
+
+///
+/// The site's fully qualified domain - the host within the zone, or the zone's apex if there's
+/// no host.
+///
+pub fn site_domain(domain_name: &str, domain_zone: &str) -> String {
+    if domain_name.eq("") {
+        domain_zone.to_string()
+    } else {
+        format!("{}.{}", domain_name, domain_zone)
+    }
+}
+
+///
+/// Works out which stacks hold the site. A `--stack-name` is used as given. Otherwise the default
+/// names are used, unless the site was deployed under the names earlier versions of the CLI gave
+/// it - stacks can't be renamed, so we carry on with those. The certificate's stack is only looked
+/// for when given the client for its region.
+///
+async fn find_stack_names(
+    fqdn: &str,
+    stack_name: Option<&str>,
+    cfn_client: &aws_sdk_cloudformation::Client,
+    certificate_cfn_client: Option<&aws_sdk_cloudformation::Client>,
+) -> Result<StackNames, Error> {
+    if let Some(stack_name) = stack_name {
+        return StackNames::custom(stack_name);
+    }
+
+    let default = StackNames::default_for(fqdn);
+    let legacy = StackNames::legacy_for(fqdn);
+    let site = stack_name_in_use(&default.site, &legacy.site, fqdn, cfn_client).await?;
+    let certificate = match certificate_cfn_client {
+        Some(certificate_cfn_client) => {
+            stack_name_in_use(
+                &default.certificate,
+                &legacy.certificate,
+                fqdn,
+                certificate_cfn_client,
+            )
+            .await?
+        }
+        None => default.certificate,
+    };
+    Ok(StackNames { site, certificate })
+}
+
+///
+/// Picks the legacy name for a stack if that's where the site already is, or the default name
+/// otherwise. Legacy names can collide, so a stack under one is only adopted if it was deployed
+/// for this domain.
+///
+async fn stack_name_in_use(
+    default: &str,
+    legacy: &str,
+    fqdn: &str,
+    cfn_client: &aws_sdk_cloudformation::Client,
+) -> Result<String, Error> {
+    if cloudformation_helpers::find_stack_id(default, cfn_client)
+        .await?
+        .is_some()
+        || cloudformation_helpers::find_stack_id(legacy, cfn_client)
+            .await?
+            .is_none()
+    {
+        return Ok(default.to_string());
+    }
+
+    let domain =
+        cloudformation_helpers::stack_parameter_value(legacy, "DomainName", cfn_client).await?;
+    if domain.as_deref() == Some(fqdn) {
+        info!(
+            name = legacy,
+            "Found the site's stack under its old name; carrying on with it"
+        );
+        Ok(legacy.to_string())
+    } else {
+        warn!(
+            name = legacy,
+            domain = domain.unwrap_or_default(),
+            "A stack with the site's old name belongs to another domain; leaving it alone"
+        );
+        Ok(default.to_string())
+    }
+}
@@ -0,0 +1,108 @@
+use super::deploy::deploy;
+use super::destroy::destroy;
+use super::site_domain;
+use crate::cloudformation_helpers;
+use crate::error::Error;
+use crate::preview::{parse_expiry, Preview};
+use crate::stack_names::StackNames;
+use crate::tags::EXPIRES_TAG;
+use crate::{clients, DestroyArgs, PreviewArgs, PreviewGcArgs, SiteArgs};
+use std::time::{Duration, SystemTime};
+use tracing::{error, info, warn};
+
+///
+/// Deploys a preview of the site under its own name, tagged with when it expires, then writes out
+/// where it can be found as JSON.
+///
+pub async fn preview(args: &mut PreviewArgs) -> Result<(), Error> {
+    let id = args.id.as_ref().ok_or(Error::MissingSetting {
+        flag: "--id".to_string(),
+    })?;
+    let preview = Preview::new(
+        id,
+        &args.deploy.site.domain_name,
+        Duration::from_secs(60 * 60 * args.ttl_hours),
+    )?;
+    let fqdn = site_domain(&preview.domain_name, args.deploy.site.domain_zone()?);
+
+    args.deploy.site.domain_name = preview.domain_name.clone();
+    args.deploy.preview_tags = preview.tags();
+    deploy(&args.deploy).await?;
+
+    println!("{}", preview.to_json(&fqdn));
+    Ok(())
+}
+
+///
+/// Destroys the previews whose time is up, in each of the regions asked for. A preview that
+/// can't be destroyed doesn't stop the rest from being.
+///
+pub async fn preview_gc(args: &PreviewGcArgs) -> Result<(), Error> {
+    let now = SystemTime::now();
+    let mut failed = vec![];
+    for region in &args.search.regions() {
+        args.search.partition.validate(region)?;
+        let cfn_client = aws_sdk_cloudformation::Client::new(
+            &clients::load_config(region.clone(), args.search.profile.as_deref()).await,
+        );
+        for stack in cloudformation_helpers::site_stacks(&cfn_client).await? {
+            let (expires, fqdn) = match (stack.tags.get(EXPIRES_TAG), &stack.domain_name) {
+                (Some(expires), Some(fqdn)) => (expires, fqdn),
+                _ => continue,
+            };
+            match parse_expiry(expires) {
+                Some(expiry) if expiry <= now => {}
+                Some(_) => {
+                    info!(domain = fqdn, expires = expires, "Preview hasn't expired");
+                    continue;
+                }
+                None => {
+                    warn!(
+                        name = &stack.name,
+                        expires = expires,
+                        "Can't tell when the preview expires; leaving it"
+                    );
+                    continue;
+                }
+            }
+            if args.dry_run {
+                info!(domain = fqdn, expires = expires, "Preview has expired");
+                continue;
+            }
+
+            info!(
+                domain = fqdn,
+                expires = expires,
+                "Destroying expired preview"
+            );
+            let destroy_args = DestroyArgs {
+                site: SiteArgs {
+                    domain_name: String::new(),
+                    domain_zone: Some(fqdn.clone()),
+                    stack_name: (stack.name != StackNames::default_for(fqdn).site)
+                        .then(|| stack.name.clone()),
+                    partition: args.search.partition,
+                    bucket_region: Some(region.clone()),
+                    profile: args.search.profile.clone(),
+                },
+                confirm: Some(fqdn.clone()),
+                unprotect: false,
+                wait: args.wait.clone(),
+            };
+            if let Err(e) = destroy(&destroy_args).await {
+                error!(
+                    domain = fqdn,
+                    msg = e.to_string(),
+                    "Couldn't destroy the preview"
+                );
+                failed.push(fqdn.clone());
+            }
+        }
+    }
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::PreviewsNotDestroyed { previews: failed })
+    }
+}
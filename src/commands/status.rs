@@ -0,0 +1,124 @@
+use super::find_stack_names;
+use crate::clients::Clients;
+use crate::cloudformation_helpers::{self, StackState};
+use crate::cloudfront_helpers;
+use crate::error::Error;
+use crate::template;
+use crate::StatusArgs;
+use aws_sdk_cloudformation::types::{ResourceStatus, StackStatus};
+use tracing::{info, warn};
+
+///
+/// Reports on a deployed site: where its stacks have got to, its certificate, its distribution,
+/// and when it was last deployed.
+///
+pub async fn status(args: &StatusArgs) -> Result<(), Error> {
+    let clients = Clients::new(
+        args.site.partition,
+        &args.site.bucket_region,
+        args.site.profile.as_deref(),
+    )
+    .await?;
+    let fqdn = args.site.fqdn()?;
+    let separate_certificate = clients.separate_certificate();
+    let stack_names = find_stack_names(
+        &fqdn,
+        args.site.stack_name.as_deref(),
+        &clients.cfn,
+        separate_certificate.then_some(&clients.certificate_cfn),
+    )
+    .await?;
+
+    let stack = match cloudformation_helpers::stack_summary(&stack_names.site, &clients.cfn).await?
+    {
+        Some(stack) => stack,
+        None => {
+            return Err(Error::StackNotFound {
+                stack_name: stack_names.site,
+            })
+        }
+    };
+    info!(
+        name = &stack.name,
+        status = stack.status.as_str(),
+        protected = stack.protected,
+        last_deployed = stack.last_deployed.as_deref().unwrap_or_default(),
+        "Stack"
+    );
+    match StackState::from(&stack.status) {
+        StackState::InProgress(_) => info!("The stack is part way through a deploy"),
+        StackState::Dead(_) => {
+            warn!("The stack can't be deployed to; deploy with --recover to recreate it")
+        }
+        StackState::Stuck(StackStatus::DeleteFailed) => {
+            warn!("The stack's deletion failed part way; run destroy again to finish it off")
+        }
+        StackState::Stuck(_) => warn!("The stack needs fixing in the CloudFormation console"),
+        StackState::Missing | StackState::Ready => {}
+    }
+
+    // The certificate is in the site's stack, unless the bucket is outside the control region
+    let (certificate_stack_name, certificate_cfn_client) = if separate_certificate {
+        match cloudformation_helpers::stack_summary(
+            &stack_names.certificate,
+            &clients.certificate_cfn,
+        )
+        .await?
+        {
+            Some(certificate_stack) => info!(
+                name = &certificate_stack.name,
+                status = certificate_stack.status.as_str(),
+                protected = certificate_stack.protected,
+                "Certificate stack"
+            ),
+            None => warn!(
+                name = &stack_names.certificate,
+                "The site's certificate stack is missing"
+            ),
+        }
+        (&stack_names.certificate, &clients.certificate_cfn)
+    } else {
+        (&stack_names.site, &clients.cfn)
+    };
+    match cloudformation_helpers::stack_resource(
+        certificate_stack_name,
+        template::CERTIFICATE_LOGICAL_ID,
+        certificate_cfn_client,
+    )
+    .await?
+    {
+        Some(certificate) => {
+            info!(
+                arn = certificate.physical_resource_id().unwrap_or_default(),
+                status = certificate
+                    .resource_status()
+                    .map(|status| status.as_str())
+                    .unwrap_or_default(),
+                "Certificate"
+            );
+            if certificate.resource_status() == Some(&ResourceStatus::CreateInProgress) {
+                info!("The certificate is waiting on its DNS validation records");
+            }
+        }
+        None => {
+            info!("The stack has no certificate of the CLI's; it may use one from its template")
+        }
+    }
+
+    match stack.outputs.get("Distribution") {
+        Some(distribution_id) => {
+            let distribution =
+                cloudfront_helpers::distribution_state(distribution_id, &clients.cloudfront)
+                    .await?;
+            info!(
+                id = distribution_id,
+                domain_name = &distribution.domain_name,
+                status = &distribution.status,
+                enabled = distribution.enabled,
+                "Distribution"
+            );
+        }
+        None => warn!("The stack has no distribution yet"),
+    }
+    Ok(())
+}
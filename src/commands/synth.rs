@@ -0,0 +1,129 @@
+use super::{deployed_bucket_name, find_stack_names, resolve_site};
+use crate::clients::Clients;
+use crate::cloudformation_helpers;
+use crate::error::Error;
+use crate::template;
+use crate::{template_validation, SynthArgs, TemplateFormat};
+use std::fs;
+use tracing::{info, warn};
+
+///
+/// Writes out the template, and the parameter values, that deploying the site with the same
+/// arguments would use.
+///
+pub async fn synth(args: &SynthArgs) -> Result<(), Error> {
+    let clients = Clients::new(
+        args.site.partition,
+        &args.site.bucket_region,
+        args.site.profile.as_deref(),
+    )
+    .await?;
+    let separate_certificate = clients.separate_certificate();
+    if args.certificate && !separate_certificate {
+        return Err(Error::NoCertificateStack);
+    }
+
+    let mut site = resolve_site(&args.site, separate_certificate, &clients.r53).await?;
+    let stack_names = find_stack_names(
+        &site.fqdn,
+        args.site.stack_name.as_deref(),
+        &clients.cfn,
+        separate_certificate.then_some(&clients.certificate_cfn),
+    )
+    .await?;
+    site.config.bucket_name = deployed_bucket_name(&stack_names.site, &clients.cfn).await?;
+    let (stack_template, parameters) = if args.certificate {
+        (
+            template::render_certificate_template(&site.config)?,
+            cloudformation_helpers::stack_parameters(site.zone_id.as_deref(), &site.fqdn, None),
+        )
+    } else {
+        // The certificate's ARN comes from its own stack, so we can only fill it in once that's
+        // been deployed
+        let certificate_arn = if separate_certificate {
+            let certificate_stack_name = stack_names.certificate.clone();
+            match cloudformation_helpers::find_stack_id(
+                &certificate_stack_name,
+                &clients.certificate_cfn,
+            )
+            .await?
+            {
+                Some(_) => Some(
+                    cloudformation_helpers::get_stack_output(
+                        &certificate_stack_name,
+                        &clients.certificate_cfn,
+                        "CertificateArn",
+                    )
+                    .await?,
+                ),
+                None => {
+                    warn!(
+                        name = &certificate_stack_name,
+                        "The certificate's stack hasn't been deployed, so the CertificateArn parameter is left out"
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        (
+            template::render_template(&site.fqdn, &site.config)?,
+            cloudformation_helpers::stack_parameters(
+                site.zone_id.as_deref(),
+                &site.fqdn,
+                certificate_arn.as_deref(),
+            ),
+        )
+    };
+
+    // Only the local checks, as there's nothing to deploy. The deploy would supply the
+    // certificate's ARN, even if we couldn't look it up here.
+    let required_outputs: &[&str] = if args.certificate {
+        &template_validation::CERTIFICATE_OUTPUTS
+    } else {
+        &template_validation::SITE_OUTPUTS
+    };
+    let mut supplied_parameters: Vec<String> = parameters
+        .iter()
+        .filter_map(|p| p.parameter_key().map(|k| k.to_string()))
+        .collect();
+    if separate_certificate && !args.certificate {
+        supplied_parameters.push("CertificateArn".to_string());
+    }
+    template_validation::check_template(
+        &stack_template.to_yaml(),
+        required_outputs,
+        &supplied_parameters,
+    )?;
+
+    let template_body = match args.format {
+        TemplateFormat::Yaml => stack_template.to_yaml(),
+        TemplateFormat::Json => stack_template.to_json(),
+    };
+    match &args.output {
+        Some(path) => {
+            fs::write(path, template_body)?;
+            info!(path = path, "Wrote template");
+        }
+        None => print!("{}", template_body),
+    }
+
+    match &args.parameters_output {
+        Some(path) => {
+            fs::write(path, cloudformation_helpers::parameters_json(&parameters))?;
+            info!(path = path, "Wrote parameters");
+        }
+        None => {
+            for parameter in &parameters {
+                info!(
+                    key = parameter.parameter_key(),
+                    value = parameter.parameter_value(),
+                    "Parameter"
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,503 @@
+use crate::partition::Partition;
+use crate::stack_names::StackNames;
+use crate::tags;
+use crate::template::{CacheBehavior, CustomHeader, ErrorPage};
+use crate::Error;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// The config file we look for in the current directory when `--config` isn't given
+pub const DEFAULT_CONFIG_FILE: &str = "staticwebsite.toml";
+
+///
+/// Settings for a site, for those that are the same on every deploy and tedious to pass each
+/// time. Each one stands in for the command line option of the same name, which takes precedence
+/// when it's given. Repeatable options take lists, written as they would be on the command line -
+/// `cache_behaviors = ["/api/*=disabled"]`, say.
+///
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    pub domain_zone: Option<String>,
+    pub domain_name: Option<String>,
+    pub zone_id: Option<String>,
+    pub stack_name: Option<String>,
+    pub partition: Option<Partition>,
+    pub bucket_region: Option<String>,
+    pub aliases: Option<Vec<String>>,
+    /// The directory to deploy, relative to wherever the CLI is run from
+    pub deploy: Option<String>,
+    pub redirect_to_canonical: Option<bool>,
+    pub external_dns: Option<bool>,
+    pub caa: Option<bool>,
+    pub caa_issuers: Option<Vec<String>>,
+    pub caa_iodef: Option<String>,
+    #[serde(default, deserialize_with = "parsed_list")]
+    pub cache_behaviors: Option<Vec<CacheBehavior>>,
+    #[serde(default, deserialize_with = "parsed_list")]
+    pub error_pages: Option<Vec<ErrorPage>>,
+    pub redirect_to_https: Option<bool>,
+    pub security_headers: Option<bool>,
+    #[serde(default, deserialize_with = "parsed_list")]
+    pub headers: Option<Vec<CustomHeader>>,
+    pub no_logging: Option<bool>,
+    pub log_retention_days: Option<u32>,
+    pub web_acl_arn: Option<String>,
+    pub environment: Option<String>,
+    pub protect: Option<bool>,
+    /// The profile, from the AWS config and credentials files, to deploy with
+    pub profile: Option<String>,
+    pub skip_verify: Option<bool>,
+    pub verify_timeout: Option<u64>,
+    pub validation_timeout: Option<u64>,
+    pub invalidation_timeout: Option<u64>,
+    pub stack_timeout: Option<u64>,
+    pub poll_interval: Option<u64>,
+    pub max_poll_interval: Option<u64>,
+    /// Tags for the site's stacks, under `[tags]`
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+}
+
+impl Settings {
+    ///
+    /// These settings, with any that `overrides` has taking their place. Tags are merged, with
+    /// those in `overrides` winning.
+    ///
+    fn overlay(&self, overrides: &Settings) -> Settings {
+        let mut tags = self.tags.clone();
+        tags.extend(overrides.tags.clone());
+
+        Settings {
+            domain_zone: overrides.domain_zone.clone().or(self.domain_zone.clone()),
+            domain_name: overrides.domain_name.clone().or(self.domain_name.clone()),
+            zone_id: overrides.zone_id.clone().or(self.zone_id.clone()),
+            stack_name: overrides.stack_name.clone().or(self.stack_name.clone()),
+            partition: overrides.partition.or(self.partition),
+            bucket_region: overrides
+                .bucket_region
+                .clone()
+                .or(self.bucket_region.clone()),
+            aliases: overrides.aliases.clone().or(self.aliases.clone()),
+            deploy: overrides.deploy.clone().or(self.deploy.clone()),
+            redirect_to_canonical: overrides
+                .redirect_to_canonical
+                .or(self.redirect_to_canonical),
+            external_dns: overrides.external_dns.or(self.external_dns),
+            caa: overrides.caa.or(self.caa),
+            caa_issuers: overrides.caa_issuers.clone().or(self.caa_issuers.clone()),
+            caa_iodef: overrides.caa_iodef.clone().or(self.caa_iodef.clone()),
+            cache_behaviors: overrides
+                .cache_behaviors
+                .clone()
+                .or(self.cache_behaviors.clone()),
+            error_pages: overrides.error_pages.clone().or(self.error_pages.clone()),
+            redirect_to_https: overrides.redirect_to_https.or(self.redirect_to_https),
+            security_headers: overrides.security_headers.or(self.security_headers),
+            headers: overrides.headers.clone().or(self.headers.clone()),
+            no_logging: overrides.no_logging.or(self.no_logging),
+            log_retention_days: overrides.log_retention_days.or(self.log_retention_days),
+            web_acl_arn: overrides.web_acl_arn.clone().or(self.web_acl_arn.clone()),
+            environment: overrides.environment.clone().or(self.environment.clone()),
+            protect: overrides.protect.or(self.protect),
+            profile: overrides.profile.clone().or(self.profile.clone()),
+            skip_verify: overrides.skip_verify.or(self.skip_verify),
+            verify_timeout: overrides.verify_timeout.or(self.verify_timeout),
+            validation_timeout: overrides.validation_timeout.or(self.validation_timeout),
+            invalidation_timeout: overrides.invalidation_timeout.or(self.invalidation_timeout),
+            stack_timeout: overrides.stack_timeout.or(self.stack_timeout),
+            poll_interval: overrides.poll_interval.or(self.poll_interval),
+            max_poll_interval: overrides.max_poll_interval.or(self.max_poll_interval),
+            tags,
+        }
+    }
+}
+
+///
+/// Reads a list of values written as they would be given on the command line, parsing each the
+/// same way.
+///
+fn parsed_list<'de, D, T>(deserializer: D) -> Result<Option<Vec<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    let values: Option<Vec<String>> = Option::deserialize(deserializer)?;
+    values
+        .map(|values| {
+            values
+                .iter()
+                .map(|value| {
+                    value
+                        .parse()
+                        .map_err(|e| D::Error::custom(format!("{}: {}", value, e)))
+                })
+                .collect()
+        })
+        .transpose()
+}
+
+///
+/// Whether an environment is production, whose stacks are protected. `prod` is taken to mean
+/// production too.
+///
+pub fn is_production(environment: &str) -> bool {
+    matches!(environment.to_lowercase().as_str(), "production" | "prod")
+}
+
+///
+/// A `staticwebsite.toml` file. Settings at the top level are defaults for every deploy; those
+/// under `[environments.<name>]` apply on top of them when deploying with `--env <name>`.
+///
+#[derive(Clone, Debug, Default)]
+pub struct ConfigFile {
+    /// Where the file was read from, or None if there isn't one
+    pub path: Option<String>,
+    pub defaults: Settings,
+    pub environments: BTreeMap<String, Settings>,
+}
+
+impl ConfigFile {
+    ///
+    /// The settings for the given environment, or just the defaults without one. An environment
+    /// is taken to be the `--environment` it's named for, unless it says otherwise - so one named
+    /// `production`, or `prod`, is protected.
+    ///
+    pub fn settings(&self, env: Option<&str>) -> Result<Settings, Error> {
+        let env = match env {
+            Some(env) => env,
+            None => return Ok(self.defaults.clone()),
+        };
+        let path = match &self.path {
+            Some(path) => path,
+            None => {
+                return Err(Error::NoConfigFile {
+                    path: DEFAULT_CONFIG_FILE.to_string(),
+                })
+            }
+        };
+
+        match self.environments.get(env) {
+            Some(environment) => {
+                let mut settings = self.defaults.overlay(environment);
+                settings.environment = settings.environment.or(Some(env.to_string()));
+                Ok(settings)
+            }
+            None => Err(Error::UnknownEnvironment {
+                env: env.to_string(),
+                path: path.clone(),
+                known: self.environments.keys().cloned().collect(),
+            }),
+        }
+    }
+
+    ///
+    /// Checks the settings for each environment - or just the defaults, if there are none - would
+    /// deploy, as far as we can tell without calling AWS. Returns the problems found.
+    ///
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        let mut stack_names: BTreeMap<String, String> = BTreeMap::new();
+
+        let names: Vec<Option<&str>> = if self.environments.is_empty() {
+            vec![None]
+        } else {
+            self.environments
+                .keys()
+                .map(|name| Some(name.as_str()))
+                .collect()
+        };
+        for name in names {
+            let settings = match self.settings(name) {
+                Ok(settings) => settings,
+                Err(e) => {
+                    problems.push(e.to_string());
+                    continue;
+                }
+            };
+            let label = match name {
+                Some(name) => format!("environment {}", name),
+                None => "defaults".to_string(),
+            };
+
+            // Without environments, the file may only hold settings shared between sites
+            let domain_zone = match &settings.domain_zone {
+                Some(domain_zone) => domain_zone,
+                None => {
+                    if name.is_some() {
+                        problems.push(format!("{}: there's no domain_zone", label));
+                    }
+                    continue;
+                }
+            };
+            let fqdn = match settings.domain_name.as_deref() {
+                None | Some("") => domain_zone.clone(),
+                Some(domain_name) => format!("{}.{}", domain_name, domain_zone),
+            };
+
+            if let Err(e) = tags::stack_tags(&settings.tags, &[], &[], &fqdn) {
+                problems.push(format!("{}: {}", label, e));
+            }
+            let partition = settings.partition.unwrap_or(Partition::Aws);
+            if let Some(bucket_region) = &settings.bucket_region {
+                if let Err(e) = partition.validate(bucket_region) {
+                    problems.push(format!("{}: {}", label, e));
+                }
+            }
+            if let Some(deploy) = &settings.deploy {
+                if !Path::new(deploy).is_dir() {
+                    problems.push(format!("{}: {} isn't a directory", label, deploy));
+                }
+            }
+
+            // Environments deploying to the same stack would overwrite each other
+            let stack_name = match &settings.stack_name {
+                Some(stack_name) => match StackNames::custom(stack_name) {
+                    Ok(names) => names.site,
+                    Err(e) => {
+                        problems.push(format!("{}: {}", label, e));
+                        continue;
+                    }
+                },
+                None => StackNames::default_for(&fqdn).site,
+            };
+            let region = settings
+                .bucket_region
+                .clone()
+                .unwrap_or_else(|| partition.control_region().to_string());
+            let stack_name = format!("{} in {}", stack_name, region);
+            if let Some(other) = stack_names.insert(stack_name.clone(), label.clone()) {
+                problems.push(format!(
+                    "{} and {} both deploy to the same stack, {}",
+                    other, label, stack_name
+                ));
+            }
+        }
+
+        problems
+    }
+}
+
+///
+/// Reads the config file at `path`, or `staticwebsite.toml` in the current directory if no path
+/// is given. It's fine for the default file not to exist, but not one that was asked for.
+///
+pub fn load_config_file(path: Option<&str>) -> Result<ConfigFile, Error> {
+    let path = match path {
+        Some(path) => path.to_string(),
+        None if Path::new(DEFAULT_CONFIG_FILE).exists() => DEFAULT_CONFIG_FILE.to_string(),
+        None => return Ok(ConfigFile::default()),
+    };
+    let contents = fs::read_to_string(&path)?;
+    let mut config_file =
+        parse_config_file(&contents).map_err(|source| Error::ConfigFileError {
+            path: path.clone(),
+            source,
+        })?;
+    config_file.path = Some(path);
+    Ok(config_file)
+}
+
+fn parse_config_file(contents: &str) -> Result<ConfigFile, toml::de::Error> {
+    // The environments sit alongside the defaults, so are split off before reading the rest
+    let mut table: toml::Table = toml::from_str(contents)?;
+    let environments = match table.remove("environments") {
+        Some(environments) => environments.try_into()?,
+        None => BTreeMap::new(),
+    };
+
+    Ok(ConfigFile {
+        path: None,
+        defaults: toml::Value::Table(table).try_into()?,
+        environments,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENVIRONMENTS: &str = "
+domain_zone = \"example.com\"
+deploy = \".\"
+
+[tags]
+team = \"web\"
+
+[environments.dev]
+domain_name = \"dev\"
+profile = \"dev-account\"
+
+[environments.production]
+domain_name = \"www\"
+aliases = [\"example.com\"]
+partition = \"aws\"
+
+[environments.production.tags]
+team = \"platform\"
+";
+
+    fn parse(contents: &str) -> ConfigFile {
+        let mut config_file = parse_config_file(contents).unwrap();
+        config_file.path = Some(DEFAULT_CONFIG_FILE.to_string());
+        config_file
+    }
+
+    #[test]
+    fn reads_tags() {
+        let config = parse_config_file(
+            "
+[tags]
+team = \"web\"
+\"cost-centre\" = \"1234\"
+",
+        )
+        .unwrap();
+        assert_eq!(config.defaults.tags.get("team"), Some(&"web".to_string()));
+        assert_eq!(
+            config.defaults.tags.get("cost-centre"),
+            Some(&"1234".to_string())
+        );
+    }
+
+    #[test]
+    fn an_empty_file_is_fine() {
+        let config = parse_config_file("").unwrap();
+        assert!(config.defaults.tags.is_empty());
+        assert!(config.environments.is_empty());
+    }
+
+    #[test]
+    fn rejects_unknown_settings() {
+        assert!(parse_config_file("[tag]\nteam = \"web\"\n").is_err());
+        assert!(parse_config_file("[tags]\nteam = 1\n").is_err());
+        assert!(parse_config_file("[environments.dev]\ndomain = \"dev\"\n").is_err());
+        assert!(parse_config_file("partition = \"aws-moon\"\n").is_err());
+    }
+
+    #[test]
+    fn reads_repeatable_options_as_they_are_given_on_the_command_line() {
+        let config = parse_config_file(
+            "
+no_logging = true
+stack_timeout = 60
+cache_behaviors = [\"/api/*=disabled\"]
+error_pages = [\"404=/index.html:200\"]
+headers = [\"Permissions-Policy: camera=()\"]
+
+[environments.dev]
+no_logging = false
+",
+        )
+        .unwrap();
+        assert_eq!(
+            config.defaults.cache_behaviors,
+            Some(vec!["/api/*=disabled".parse().unwrap()])
+        );
+        assert_eq!(
+            config.defaults.error_pages,
+            Some(vec!["404=/index.html:200".parse().unwrap()])
+        );
+        assert_eq!(
+            config.defaults.headers,
+            Some(vec!["Permissions-Policy: camera=()".parse().unwrap()])
+        );
+        assert_eq!(config.defaults.stack_timeout, Some(60));
+        assert_eq!(config.environments["dev"].no_logging, Some(false));
+
+        assert!(parse_config_file("cache_behaviors = [\"/api/*\"]\n").is_err());
+        assert!(parse_config_file("error_pages = [\"not-a-code=/x.html\"]\n").is_err());
+    }
+
+    #[test]
+    fn prod_is_production() {
+        assert!(is_production("production"));
+        assert!(is_production("prod"));
+        assert!(is_production("Production"));
+        assert!(!is_production("staging"));
+        assert!(!is_production("preview"));
+    }
+
+    #[test]
+    fn environments_override_the_defaults() {
+        let config = parse(ENVIRONMENTS);
+
+        let dev = config.settings(Some("dev")).unwrap();
+        assert_eq!(dev.domain_zone, Some("example.com".to_string()));
+        assert_eq!(dev.domain_name, Some("dev".to_string()));
+        assert_eq!(dev.profile, Some("dev-account".to_string()));
+        assert_eq!(dev.environment, Some("dev".to_string()));
+        assert_eq!(dev.tags.get("team"), Some(&"web".to_string()));
+
+        let production = config.settings(Some("production")).unwrap();
+        assert_eq!(production.aliases, Some(vec!["example.com".to_string()]));
+        assert_eq!(production.partition, Some(Partition::Aws));
+        assert_eq!(production.environment, Some("production".to_string()));
+        assert_eq!(production.tags.get("team"), Some(&"platform".to_string()));
+
+        assert_eq!(config.settings(None).unwrap(), config.defaults);
+        assert!(matches!(
+            config.settings(Some("staging")),
+            Err(Error::UnknownEnvironment { .. })
+        ));
+        assert!(matches!(
+            ConfigFile::default().settings(Some("dev")),
+            Err(Error::NoConfigFile { .. })
+        ));
+    }
+
+    #[test]
+    fn problems_are_reported_per_environment() {
+        assert!(parse(ENVIRONMENTS).problems().is_empty());
+        assert!(parse("[tags]\nteam = \"web\"\n").problems().is_empty());
+
+        let problems = parse(
+            "
+deploy = \"no-such-directory\"
+
+[environments.dev]
+domain_zone = \"example.com\"
+stack_name = \"site\"
+
+[environments.qa]
+domain_zone = \"example.com\"
+stack_name = \"site\"
+
+[environments.eu]
+domain_zone = \"example.com\"
+stack_name = \"site\"
+bucket_region = \"eu-west-2\"
+
+[environments.staging]
+domain_zone = \"example.com\"
+stack_name = \"site\"
+bucket_region = \"cn-north-1\"
+
+[environments.production]
+domain_name = \"www\"
+
+[environments.production.tags]
+\"aws:team\" = \"web\"
+",
+        )
+        .problems();
+        assert_eq!(
+            problems,
+            vec![
+                "environment dev: no-such-directory isn't a directory",
+                "environment eu: no-such-directory isn't a directory",
+                "environment production: there's no domain_zone",
+                "environment qa: no-such-directory isn't a directory",
+                "environment dev and environment qa both deploy to the same stack, site in us-east-1",
+                "environment staging: Can't deploy to the aws partition: cn-north-1 isn't one of its regions",
+                "environment staging: no-such-directory isn't a directory",
+            ]
+        );
+    }
+}
@@ -1,10 +1,32 @@
 use aws_sdk_cloudformation::error::SdkError;
+use aws_sdk_cloudformation::operation::create_change_set::CreateChangeSetError;
 use aws_sdk_cloudformation::operation::create_stack::CreateStackError;
+use aws_sdk_cloudformation::operation::delete_change_set::DeleteChangeSetError;
+use aws_sdk_cloudformation::operation::delete_stack::DeleteStackError;
+use aws_sdk_cloudformation::operation::describe_change_set::DescribeChangeSetError;
+use aws_sdk_cloudformation::operation::describe_stack_drift_detection_status::DescribeStackDriftDetectionStatusError;
+use aws_sdk_cloudformation::operation::describe_stack_events::DescribeStackEventsError;
+use aws_sdk_cloudformation::operation::describe_stack_resource::DescribeStackResourceError;
+use aws_sdk_cloudformation::operation::describe_stack_resource_drifts::DescribeStackResourceDriftsError;
 use aws_sdk_cloudformation::operation::describe_stacks::DescribeStacksError;
-use aws_sdk_cloudformation::operation::update_stack::UpdateStackError;
+use aws_sdk_cloudformation::operation::detect_stack_drift::DetectStackDriftError;
+use aws_sdk_cloudformation::operation::execute_change_set::ExecuteChangeSetError;
+use aws_sdk_cloudformation::operation::get_template::GetTemplateError;
+use aws_sdk_cloudformation::operation::set_stack_policy::SetStackPolicyError;
+use aws_sdk_cloudformation::operation::update_termination_protection::UpdateTerminationProtectionError;
+use aws_sdk_cloudformation::operation::validate_template::ValidateTemplateError;
 use aws_sdk_cloudfront::operation::create_invalidation::CreateInvalidationError;
+use aws_sdk_cloudfront::operation::get_distribution::GetDistributionError;
 use aws_sdk_cloudfront::operation::get_invalidation::GetInvalidationError;
+use aws_sdk_cloudfront::operation::list_tags_for_resource::ListTagsForResourceError;
+use aws_sdk_route53::operation::change_resource_record_sets::ChangeResourceRecordSetsError;
+use aws_sdk_route53::operation::get_hosted_zone::GetHostedZoneError;
 use aws_sdk_route53::operation::list_hosted_zones_by_name::ListHostedZonesByNameError;
+use aws_sdk_route53::operation::list_resource_record_sets::ListResourceRecordSetsError;
+use aws_sdk_s3::operation::delete_objects::DeleteObjectsError;
+use aws_sdk_s3::operation::get_bucket_location::GetBucketLocationError;
+use aws_sdk_s3::operation::get_bucket_tagging::GetBucketTaggingError;
+use aws_sdk_s3::operation::list_object_versions::ListObjectVersionsError;
 use aws_sdk_s3::operation::put_object::PutObjectError;
 use aws_sdk_s3::primitives::ByteStreamError;
 use aws_sdk_sts::operation::get_caller_identity::GetCallerIdentityError;
@@ -19,59 +41,288 @@ pub enum Error {
     #[error("Stack output not found")]
     StackOutputNotFound,
 
-    #[error("Encountered unusuable stack status")]
-    UnusableStackStatus,
+    #[error("Stack {stack_name} is {status}, and needs fixing in the CloudFormation console before it can be deployed to")]
+    UnusableStackStatus { stack_name: String, status: String },
+
+    #[error("Stack {stack_name} is {status}, and can only be fixed by deleting it; re-run with --recover to delete and recreate it")]
+    DeadStack { stack_name: String, status: String },
+
+    #[error("Stack {stack_name} ended up {status}: {}", failures.join("; "))]
+    StackFailed {
+        stack_name: String,
+        status: String,
+        failures: Vec<String>,
+    },
+
+    #[error("No public hosted zone named {zone} was found")]
+    ZoneNotFound { zone: String },
+
+    #[error("Multiple public hosted zones named {zone} exist ({}); pick one with --zone-id", zone_ids.join(", "))]
+    AmbiguousZone { zone: String, zone_ids: Vec<String> },
+
+    #[error("Hosted zone {zone_id} is not a public zone named {zone}")]
+    ZoneIdMismatch { zone_id: String, zone: String },
+
+    #[error("Can't use {alias} as an alias: {reason}")]
+    InvalidAlias { alias: String, reason: String },
+
+    #[error("Can't deploy to the {partition} partition: {reason}")]
+    UnsupportedPartition { partition: String, reason: String },
+
+    #[error("Invalid CAA policy: {reason}")]
+    InvalidCaaPolicy { reason: String },
+
+    #[error("Invalid site configuration: {reason}")]
+    InvalidSiteConfig { reason: String },
+
+    #[error("Can't use {stack_name} as a stack name: {reason}")]
+    InvalidStackName { stack_name: String, reason: String },
+
+    #[error("Can't invalidate {path}: {reason}")]
+    InvalidInvalidationPath { path: String, reason: String },
+
+    #[error("{stack_name} is protected; re-run with --unprotect to remove its protection first")]
+    StackProtected { stack_name: String },
+
+    #[error("{stack_name} is protected, and the update would replace or delete {resources}; re-run with --unprotect to allow it")]
+    ProtectedResourceChange {
+        stack_name: String,
+        resources: String,
+    },
+
+    #[error("Can't adopt resources into {stack_name}, as it already exists; resources can only be adopted into a new stack")]
+    AdoptIntoExistingStack { stack_name: String },
+
+    #[error("The resources can't be adopted: {}", problems.join("; "))]
+    IncompatibleResources { problems: Vec<String> },
+
+    #[error("--max-poll-interval ({max_poll_interval}s) can't be shorter than --poll-interval ({poll_interval}s)")]
+    InvalidPollInterval {
+        poll_interval: u64,
+        max_poll_interval: u64,
+    },
+
+    #[error("Can't use the tag {key}: {reason}")]
+    InvalidTag { key: String, reason: String },
+
+    #[error("Couldn't read the config file {path}")]
+    ConfigFileError {
+        path: String,
+        #[source]
+        source: toml::de::Error,
+    },
+
+    #[error("Can't use {id} as a preview ID: {reason}")]
+    InvalidPreviewId { id: String, reason: String },
+
+    #[error("{flag} can't be used when deploying a preview")]
+    PreviewOption { flag: String },
+
+    #[error("Couldn't destroy some expired previews: {}", previews.join(", "))]
+    PreviewsNotDestroyed { previews: Vec<String> },
+
+    #[error("There's no config file at {path} to take environments from")]
+    NoConfigFile { path: String },
+
+    #[error("{path} has no environment named {env}; it has: {}", known.join(", "))]
+    UnknownEnvironment {
+        env: String,
+        path: String,
+        known: Vec<String>,
+    },
+
+    #[error("The config file {path} has problems: {}", problems.join("; "))]
+    InvalidConfigFile { path: String, problems: Vec<String> },
+
+    #[error("No {flag} was given, on the command line or in the config file")]
+    MissingSetting { flag: String },
+
+    #[error("The template isn't valid: {}", problems.join("; "))]
+    InvalidTemplate { problems: Vec<String> },
+
+    #[error("The site's certificate only has a stack of its own when --bucket-region is outside the partition's control region")]
+    NoCertificateStack,
+
+    #[error("The site couldn't be verified: {}", failures.join("; "))]
+    VerificationFailed { failures: Vec<String> },
+
+    #[error("Couldn't work out the changes to the stack: {reason}")]
+    ChangeSetFailed { reason: String },
+
+    #[error("The stack's changes weren't confirmed, so nothing was changed")]
+    ChangesDeclined,
+
+    #[error("Timed out waiting for {operation}; still pending: {}. {hint}", pending.join("; "))]
+    StillPending {
+        operation: String,
+        pending: Vec<String>,
+        hint: String,
+    },
+
+    #[error("No stack named {stack_name} was found")]
+    StackNotFound { stack_name: String },
+
+    #[error("Couldn't empty bucket {bucket}: {reason}")]
+    EmptyBucketFailed { bucket: String, reason: String },
+
+    #[error("The domain wasn't confirmed, so nothing was destroyed")]
+    DestroyNotConfirmed,
+
+    #[error("{count} existing DNS record(s) conflict with the site; re-run with --takeover to replace them")]
+    ConflictingRecords { count: usize },
 
     ///
     /// AWS SDK errors
     ///
 
-    #[error("UpdateStack failed")]
-    UpdateStackError {
-        #[from]
-        source: SdkError<UpdateStackError>,
+    #[error("CreateChangeSet failed")]
+    CreateChangeSetError {
+        source: Box<SdkError<CreateChangeSetError>>,
+    },
+
+    #[error("DescribeChangeSet failed")]
+    DescribeChangeSetError {
+        source: Box<SdkError<DescribeChangeSetError>>,
+    },
+
+    #[error("ExecuteChangeSet failed")]
+    ExecuteChangeSetError {
+        source: Box<SdkError<ExecuteChangeSetError>>,
+    },
+
+    #[error("DeleteChangeSet failed")]
+    DeleteChangeSetError {
+        source: Box<SdkError<DeleteChangeSetError>>,
     },
 
     #[error("ListHostedZonesByName failed")]
     ListHostedZonesByNameError {
-        #[from]
-        source: SdkError<ListHostedZonesByNameError>,
+        source: Box<SdkError<ListHostedZonesByNameError>>,
+    },
+
+    #[error("GetHostedZone failed")]
+    GetHostedZoneError {
+        source: Box<SdkError<GetHostedZoneError>>,
+    },
+
+    #[error("ListResourceRecordSets failed")]
+    ListResourceRecordSetsError {
+        source: Box<SdkError<ListResourceRecordSetsError>>,
+    },
+
+    #[error("ChangeResourceRecordSets failed")]
+    ChangeResourceRecordSetsError {
+        source: Box<SdkError<ChangeResourceRecordSetsError>>,
     },
 
     #[error("CreateStack failed")]
     CreateStackError {
-        #[from]
-        source: SdkError<CreateStackError>,
+        source: Box<SdkError<CreateStackError>>,
     },
 
     #[error("DescribeStacks failed")]
     DescribeStacksError {
-        #[from]
-        source: SdkError<DescribeStacksError>,
+        source: Box<SdkError<DescribeStacksError>>,
+    },
+
+    #[error("DescribeStackResource failed")]
+    DescribeStackResourceError {
+        source: Box<SdkError<DescribeStackResourceError>>,
+    },
+
+    #[error("DeleteStack failed")]
+    DeleteStackError {
+        source: Box<SdkError<DeleteStackError>>,
+    },
+
+    #[error("DescribeStackEvents failed")]
+    DescribeStackEventsError {
+        source: Box<SdkError<DescribeStackEventsError>>,
+    },
+
+    #[error("DetectStackDrift failed")]
+    DetectStackDriftError {
+        source: Box<SdkError<DetectStackDriftError>>,
+    },
+
+    #[error("DescribeStackDriftDetectionStatus failed")]
+    DescribeStackDriftDetectionStatusError {
+        source: Box<SdkError<DescribeStackDriftDetectionStatusError>>,
+    },
+
+    #[error("DescribeStackResourceDrifts failed")]
+    DescribeStackResourceDriftsError {
+        source: Box<SdkError<DescribeStackResourceDriftsError>>,
+    },
+
+    #[error("GetTemplate failed")]
+    GetTemplateError {
+        source: Box<SdkError<GetTemplateError>>,
+    },
+
+    #[error("UpdateTerminationProtection failed")]
+    UpdateTerminationProtectionError {
+        source: Box<SdkError<UpdateTerminationProtectionError>>,
+    },
+
+    #[error("SetStackPolicy failed")]
+    SetStackPolicyError {
+        source: Box<SdkError<SetStackPolicyError>>,
+    },
+
+    #[error("ValidateTemplate failed")]
+    ValidateTemplateError {
+        source: Box<SdkError<ValidateTemplateError>>,
     },
 
     #[error("GetCallerIdentity failed")]
     GetCallerIdentityError {
-        #[from]
-        source: SdkError<GetCallerIdentityError>,
+        source: Box<SdkError<GetCallerIdentityError>>,
     },
 
     #[error("PutObject failed")]
     PutObjectError {
-        #[from]
-        source: SdkError<PutObjectError>,
+        source: Box<SdkError<PutObjectError>>,
+    },
+
+    #[error("ListObjectVersions failed")]
+    ListObjectVersionsError {
+        source: Box<SdkError<ListObjectVersionsError>>,
+    },
+
+    #[error("DeleteObjects failed")]
+    DeleteObjectsError {
+        source: Box<SdkError<DeleteObjectsError>>,
     },
 
     #[error("CreateInvalidation failed")]
     CreateInvalidationError {
-        #[from]
-        source: SdkError<CreateInvalidationError>,
+        source: Box<SdkError<CreateInvalidationError>>,
     },
 
     #[error("GetInvalidation failed")]
     GetInvalidationError {
-        #[from]
-        source: SdkError<GetInvalidationError>,
+        source: Box<SdkError<GetInvalidationError>>,
+    },
+
+    #[error("GetDistribution failed")]
+    GetDistributionError {
+        source: Box<SdkError<GetDistributionError>>,
+    },
+
+    #[error("ListTagsForResource failed")]
+    ListTagsForResourceError {
+        source: Box<SdkError<ListTagsForResourceError>>,
+    },
+
+    #[error("GetBucketLocation failed")]
+    GetBucketLocationError {
+        source: Box<SdkError<GetBucketLocationError>>,
+    },
+
+    #[error("GetBucketTagging failed")]
+    GetBucketTaggingError {
+        source: Box<SdkError<GetBucketTaggingError>>,
     },
 
     ///
@@ -89,6 +340,12 @@ pub enum Error {
         source: SetGlobalDefaultError,
     },
 
+    #[error("Couldn't parse CloudFormation template")]
+    TemplateParseError {
+        #[from]
+        source: serde_yaml::Error,
+    },
+
     #[error("IO Error")]
     IOError {
         #[from]
@@ -101,3 +358,54 @@ pub enum Error {
         source: tokio::time::error::Elapsed,
     },
 }
+
+///
+/// The SDK's errors are large, so they're boxed to keep `Error` small. These stand in for the
+/// `From` impls `#[from]` would otherwise generate, so `?` still converts them.
+///
+macro_rules! boxed_sdk_errors {
+    ($($variant:ident: $error:ty,)*) => {
+        $(
+            impl From<SdkError<$error>> for Error {
+                fn from(source: SdkError<$error>) -> Self {
+                    Error::$variant {
+                        source: Box::new(source),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+boxed_sdk_errors! {
+    CreateChangeSetError: CreateChangeSetError,
+    DescribeChangeSetError: DescribeChangeSetError,
+    ExecuteChangeSetError: ExecuteChangeSetError,
+    DeleteChangeSetError: DeleteChangeSetError,
+    ListHostedZonesByNameError: ListHostedZonesByNameError,
+    GetHostedZoneError: GetHostedZoneError,
+    ListResourceRecordSetsError: ListResourceRecordSetsError,
+    ChangeResourceRecordSetsError: ChangeResourceRecordSetsError,
+    CreateStackError: CreateStackError,
+    DescribeStacksError: DescribeStacksError,
+    DescribeStackResourceError: DescribeStackResourceError,
+    DeleteStackError: DeleteStackError,
+    DescribeStackEventsError: DescribeStackEventsError,
+    DetectStackDriftError: DetectStackDriftError,
+    DescribeStackDriftDetectionStatusError: DescribeStackDriftDetectionStatusError,
+    DescribeStackResourceDriftsError: DescribeStackResourceDriftsError,
+    GetTemplateError: GetTemplateError,
+    UpdateTerminationProtectionError: UpdateTerminationProtectionError,
+    SetStackPolicyError: SetStackPolicyError,
+    ValidateTemplateError: ValidateTemplateError,
+    GetCallerIdentityError: GetCallerIdentityError,
+    PutObjectError: PutObjectError,
+    ListObjectVersionsError: ListObjectVersionsError,
+    DeleteObjectsError: DeleteObjectsError,
+    CreateInvalidationError: CreateInvalidationError,
+    GetInvalidationError: GetInvalidationError,
+    GetDistributionError: GetDistributionError,
+    ListTagsForResourceError: ListTagsForResourceError,
+    GetBucketLocationError: GetBucketLocationError,
+    GetBucketTaggingError: GetBucketTaggingError,
+}
@@ -1,41 +1,860 @@
+mod adoption;
+mod clients;
 mod cloudformation_helpers;
 mod cloudfront_helpers;
+mod commands;
+mod config;
 mod error;
+mod partition;
+mod polling;
+mod preview;
+mod prompt_helpers;
 mod route53_helpers;
 mod s3_helpers;
+mod stack_names;
+mod tags;
+mod template;
+mod template_model;
+mod template_validation;
+mod verify_helpers;
 
-use crate::cloudfront_helpers::{invalidate_distribution, wait_for_invalidation};
+use crate::commands::{
+    deploy, destroy, drift, invalidate, list, preview, preview_gc, site_domain, status, synth,
+    validate_config,
+};
+use crate::config::Settings;
 use crate::error::Error;
-use crate::s3_helpers::upload_directory;
-use clap::Parser;
-use std::fs;
-use std::path::Path;
+use crate::partition::Partition;
+use crate::polling::Backoff;
+use crate::tags::StackTag;
+use crate::template::{CacheBehavior, CustomHeader, ErrorPage};
+use aws_sdk_cloudformation::types::Tag;
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use std::collections::BTreeMap;
 use std::process::exit;
 use std::time::Duration;
 use thiserror::Error;
-use tokio::time::timeout;
 use tracing::{error, info};
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true
+)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// A config file to read settings from. Defaults to `staticwebsite.toml` in the current
+    /// directory, if there is one. Options given on the command line take precedence over it.
+    #[arg(long, global = true)]
+    config: Option<String>,
+
+    /// The environment, from the config file's `[environments]`, to take settings from. It's
+    /// taken as the site's --environment too, unless the config file says otherwise.
+    #[arg(long, global = true)]
+    env: Option<String>,
+
+    // Deploying is what happens when no command is given
+    #[command(flatten)]
+    deploy: DeployArgs,
+}
+
+///
+/// Deploys a site, creating it if need be.
+///
+#[derive(clap::Args, Debug)]
+struct DeployArgs {
+    #[command(flatten)]
+    site: SiteConfigArgs,
+
+    /// The directory to deploy
+    #[arg(long)]
+    deploy: Option<String>,
+
+    /// Deploy this CloudFormation template, in YAML or JSON, rather than the one the CLI
+    /// generates - one exported with `synth` and then customised, for instance. It must have
+    /// `StaticWebsiteBucket` and `Distribution` outputs, and is passed whichever of the
+    /// `DomainName`, `HostedZoneId` and `CertificateArn` parameters it declares.
+    #[arg(long, conflicts_with_all = [
+        "redirect_to_canonical",
+        "caa",
+        "cache_behaviors",
+        "error_pages",
+        "redirect_to_https",
+        "security_headers",
+        "headers",
+        "no_logging",
+        "log_retention_days",
+        "web_acl_arn",
+    ])]
+    template: Option<String>,
+
+    /// Adopt this existing bucket as the site's, rather than creating one. It's imported into a
+    /// new stack along with the distribution given by --adopt-distribution, and the site's
+    /// template then applied to them both.
+    #[arg(long, requires = "adopt_distribution", conflicts_with = "template")]
+    adopt_bucket: Option<String>,
+
+    /// The ID of an existing distribution serving --adopt-bucket, to adopt as the site's
+    #[arg(long, requires = "adopt_bucket")]
+    adopt_distribution: Option<String>,
+
+    /// A tag to put on the site's stacks, given as `KEY=VALUE` - e.g. `team=web`. CloudFormation
+    /// passes them on to the buckets, distribution and certificate. May be repeated; these take
+    /// precedence over tags in the config file.
+    #[arg(long = "tag")]
+    tags: Vec<StackTag>,
+
+    /// Replace any existing DNS records that conflict with the site's. Without this, the deploy
+    /// stops if another record already uses one of the site's names.
+    #[arg(long)]
+    takeover: bool,
+
+    /// Don't check that the site resolves and is reachable over HTTPS once it has deployed
+    #[arg(long, action = ArgAction::Set, num_args = 0..=1, require_equals = true, default_value_t = false, default_missing_value = "true")]
+    skip_verify: bool,
+
+    /// How long to keep retrying the post-deploy checks, in minutes, while DNS propagates
+    #[arg(long, default_value_t = 10)]
+    verify_timeout: u64,
+
+    /// Go ahead with changes that replace existing resources, such as the certificate or the
+    /// distribution, without asking for confirmation
+    #[arg(long)]
+    yes: bool,
+
+    /// The environment the site is for, e.g. `staging`. The stacks of a `production` (or `prod`)
+    /// site are protected, as if with --protect. Not to be confused with --env, which picks the
+    /// config file's settings.
+    #[arg(long)]
+    environment: Option<String>,
+
+    /// Turn on termination protection for the site's stacks, and set a stack policy stopping
+    /// updates from replacing or deleting the distribution, certificate or buckets
+    #[arg(long, action = ArgAction::Set, num_args = 0..=1, require_equals = true, default_value_t = false, default_missing_value = "true")]
+    protect: bool,
+
+    /// Remove the protection --protect, or a production --environment, puts on the site's
+    /// stacks. Without this, protection is left as it is.
+    #[arg(long, conflicts_with = "protect")]
+    unprotect: bool,
+
+    /// If a stack can only be fixed by deleting it - for instance, because its first deploy failed
+    /// and was rolled back - delete and recreate it without asking
+    #[arg(long)]
+    recover: bool,
+
+    /// How long to wait, in minutes, for the certificate's DNS validation records to be created
+    /// when using --external-dns
+    #[arg(long, default_value_t = 60)]
+    validation_timeout: u64,
+
+    /// How long to wait, in minutes, for the CDN invalidation to complete
+    #[arg(long, default_value_t = 15)]
+    invalidation_timeout: u64,
+
+    #[command(flatten)]
+    wait: WaitArgs,
+
+    /// Tags from the config file, which those given with --tag take precedence over
+    #[arg(skip)]
+    config_tags: BTreeMap<String, String>,
+
+    /// Our tags marking the site as a preview, and when it expires
+    #[arg(skip)]
+    preview_tags: Vec<Tag>,
+}
+
+///
+/// Describes a site to deploy: where it's served from, and how.
+///
+#[derive(clap::Args, Debug)]
+struct SiteConfigArgs {
     /// Domain host. If this isn't specified, we will deploy to the apex.
     #[arg(long, default_value = "")]
     domain_name: String,
 
     /// Domain zone - the zone name into which we should deploy the domain.
     #[arg(long)]
-    domain_zone: String,
+    domain_zone: Option<String>,
 
-    /// The directory to deploy
+    /// Hosted zone ID to deploy into. Use this to skip the lookup by name, for instance when
+    /// several public zones share the same name.
+    #[arg(long)]
+    zone_id: Option<String>,
+
+    /// Name the site's stack this rather than after its domain. The certificate's stack, if it
+    /// needs one, gets the same name with `-certificate` on the end.
+    #[arg(long)]
+    stack_name: Option<String>,
+
+    /// The AWS partition to deploy into. This decides the region the certificate is issued in
+    /// and Route53 and CloudFront are called from. Only aws is supported.
+    #[arg(long, value_enum, default_value_t = Partition::Aws)]
+    partition: Partition,
+
+    /// The region the website's bucket, and the stack managing it, should live in. Defaults to
+    /// the partition's control region - us-east-1 for the aws partition.
+    #[arg(long)]
+    bucket_region: Option<String>,
+
+    /// The profile, from the AWS config and credentials files, to deploy with
+    #[arg(long)]
+    profile: Option<String>,
+
+    /// An additional host name to serve the site from, e.g. `www.example.com`. May be repeated.
+    /// The alias may live in a different zone to the primary domain.
+    #[arg(long = "alias")]
+    aliases: Vec<String>,
+
+    /// Permanently redirect requests for any alias to the primary domain
+    #[arg(long, action = ArgAction::Set, num_args = 0..=1, require_equals = true, default_value_t = false, default_missing_value = "true")]
+    redirect_to_canonical: bool,
+
+    /// DNS for the domain is hosted outside Route53. No DNS records are created; instead, the
+    /// records needed to validate the certificate and reach the site are printed for you to
+    /// create.
+    #[arg(
+        long,
+        conflicts_with = "zone_id",
+        action = ArgAction::Set,
+        num_args = 0..=1,
+        require_equals = true,
+        default_value_t = false,
+        default_missing_value = "true"
+    )]
+    external_dns: bool,
+
+    /// Publish CAA records restricting which certificate authorities may issue certificates for
+    /// the site's names. Amazon's authorities are always allowed, so the site's certificate can
+    /// be issued.
+    #[arg(
+        long,
+        conflicts_with = "external_dns",
+        action = ArgAction::Set,
+        num_args = 0..=1,
+        require_equals = true,
+        default_value_t = false,
+        default_missing_value = "true"
+    )]
+    caa: bool,
+
+    /// An additional certificate authority allowed by the CAA records, e.g. `letsencrypt.org`.
+    /// May be repeated.
+    #[arg(long = "caa-issuer")]
+    caa_issuers: Vec<String>,
+
+    /// Where certificate authorities should report CAA policy violations, e.g.
+    /// `mailto:security@example.com`
+    #[arg(long)]
+    caa_iodef: Option<String>,
+
+    /// Cache requests matching a path pattern differently to the rest of the site, given as
+    /// `PATTERN=POLICY` - e.g. `/api/*=disabled`. The policy is `optimized`, `disabled` or the ID
+    /// of a CloudFront cache policy. May be repeated; earlier patterns take precedence.
+    #[arg(long = "cache-behavior")]
+    cache_behaviors: Vec<CacheBehavior>,
+
+    /// Serve a page of the site in place of an error, given as `CODE=PATH[:STATUS]` - e.g.
+    /// `404=/404.html`, or `404=/index.html:200` for a single-page app. May be repeated.
+    #[arg(long = "error-page")]
+    error_pages: Vec<ErrorPage>,
+
+    /// Redirect requests over plain HTTP to HTTPS, rather than serving them
+    #[arg(long, action = ArgAction::Set, num_args = 0..=1, require_equals = true, default_value_t = false, default_missing_value = "true")]
+    redirect_to_https: bool,
+
+    /// Add HSTS, X-Content-Type-Options, X-Frame-Options, Referrer-Policy and X-XSS-Protection
+    /// headers to every response
+    #[arg(long, action = ArgAction::Set, num_args = 0..=1, require_equals = true, default_value_t = false, default_missing_value = "true")]
+    security_headers: bool,
+
+    /// A header to add to every response, given as `NAME: VALUE`. May be repeated.
+    #[arg(long = "header")]
+    headers: Vec<CustomHeader>,
+
+    /// Don't keep access logs for the site
+    #[arg(long, action = ArgAction::Set, num_args = 0..=1, require_equals = true, default_value_t = false, default_missing_value = "true")]
+    no_logging: bool,
+
+    /// How many days to keep access logs for
+    #[arg(long, default_value_t = 90, conflicts_with = "no_logging")]
+    log_retention_days: u32,
+
+    /// The ARN of a WAF web ACL, created with CloudFront scope, to protect the site with
+    #[arg(long)]
+    web_acl_arn: Option<String>,
+}
+
+///
+/// Settings for how long, and how often, we wait on CloudFormation and CloudFront.
+///
+#[derive(clap::Args, Clone, Debug)]
+struct WaitArgs {
+    /// How long to wait, in minutes, for a stack to finish deploying or deleting. Issuing a
+    /// site's first certificate can take a while.
+    #[arg(long, default_value_t = 45)]
+    stack_timeout: u64,
+
+    /// How long to wait, in seconds, before first checking back on a long-running operation
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u64).range(1..))]
+    poll_interval: u64,
+
+    /// The longest to wait, in seconds, between checks on a long-running operation. We back off
+    /// from --poll-interval towards this as the wait goes on.
+    #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(u64).range(1..))]
+    max_poll_interval: u64,
+}
+
+impl Args {
+    ///
+    /// The wait settings for the command being run, if it waits on anything.
+    ///
+    fn wait_args(&self) -> Option<&WaitArgs> {
+        match &self.command {
+            Some(Command::Deploy(deploy_args)) => Some(&deploy_args.wait),
+            Some(Command::Invalidate(InvalidateArgs { wait, .. }))
+            | Some(Command::Destroy(DestroyArgs { wait, .. }))
+            | Some(Command::Drift(DriftArgs { wait, .. })) => Some(wait),
+            Some(Command::Preview(preview_args)) => match &preview_args.command {
+                Some(PreviewCommand::Gc(gc_args)) => Some(&gc_args.wait),
+                None => Some(&preview_args.deploy.wait),
+            },
+            Some(Command::Status(_))
+            | Some(Command::List(_))
+            | Some(Command::Synth(_))
+            | Some(Command::Config(_)) => None,
+            None => Some(&self.deploy.wait),
+        }
+    }
+}
+
+impl DeployArgs {
+    ///
+    /// Whether the site's stacks should be protected, or have their protection removed. None
+    /// leaves them as they are: protection only comes off when asked.
+    ///
+    fn protection(&self) -> Option<bool> {
+        if self.unprotect {
+            Some(false)
+        } else if self.protect
+            || self
+                .environment
+                .as_deref()
+                .is_some_and(config::is_production)
+        {
+            Some(true)
+        } else {
+            None
+        }
+    }
+}
+
+impl DeployArgs {
+    fn apply_settings(&mut self, settings: &Settings, matches: &ArgMatches) {
+        self.site.apply_settings(settings, matches);
+        self.wait.apply_settings(settings, matches);
+        self.deploy = self.deploy.take().or(settings.deploy.clone());
+        self.environment = self.environment.take().or(settings.environment.clone());
+        apply(&mut self.protect, &settings.protect, matches, "protect");
+        apply(
+            &mut self.skip_verify,
+            &settings.skip_verify,
+            matches,
+            "skip_verify",
+        );
+        apply(
+            &mut self.verify_timeout,
+            &settings.verify_timeout,
+            matches,
+            "verify_timeout",
+        );
+        apply(
+            &mut self.validation_timeout,
+            &settings.validation_timeout,
+            matches,
+            "validation_timeout",
+        );
+        apply(
+            &mut self.invalidation_timeout,
+            &settings.invalidation_timeout,
+            matches,
+            "invalidation_timeout",
+        );
+        self.config_tags = settings.tags.clone();
+    }
+}
+
+impl SiteConfigArgs {
+    fn apply_settings(&mut self, settings: &Settings, matches: &ArgMatches) {
+        apply(
+            &mut self.domain_name,
+            &settings.domain_name,
+            matches,
+            "domain_name",
+        );
+        apply(
+            &mut self.partition,
+            &settings.partition,
+            matches,
+            "partition",
+        );
+        self.domain_zone = self.domain_zone.take().or(settings.domain_zone.clone());
+        self.zone_id = self.zone_id.take().or(settings.zone_id.clone());
+        self.stack_name = self.stack_name.take().or(settings.stack_name.clone());
+        self.bucket_region = self.bucket_region.take().or(settings.bucket_region.clone());
+        self.profile = self.profile.take().or(settings.profile.clone());
+        self.caa_iodef = self.caa_iodef.take().or(settings.caa_iodef.clone());
+        self.web_acl_arn = self.web_acl_arn.take().or(settings.web_acl_arn.clone());
+        apply_list(&mut self.aliases, &settings.aliases);
+        apply_list(&mut self.caa_issuers, &settings.caa_issuers);
+        apply_list(&mut self.cache_behaviors, &settings.cache_behaviors);
+        apply_list(&mut self.error_pages, &settings.error_pages);
+        apply_list(&mut self.headers, &settings.headers);
+        apply(
+            &mut self.redirect_to_canonical,
+            &settings.redirect_to_canonical,
+            matches,
+            "redirect_to_canonical",
+        );
+        apply(
+            &mut self.external_dns,
+            &settings.external_dns,
+            matches,
+            "external_dns",
+        );
+        apply(&mut self.caa, &settings.caa, matches, "caa");
+        apply(
+            &mut self.redirect_to_https,
+            &settings.redirect_to_https,
+            matches,
+            "redirect_to_https",
+        );
+        apply(
+            &mut self.security_headers,
+            &settings.security_headers,
+            matches,
+            "security_headers",
+        );
+        apply(
+            &mut self.no_logging,
+            &settings.no_logging,
+            matches,
+            "no_logging",
+        );
+        apply(
+            &mut self.log_retention_days,
+            &settings.log_retention_days,
+            matches,
+            "log_retention_days",
+        );
+    }
+
+    fn domain_zone(&self) -> Result<&str, Error> {
+        self.domain_zone.as_deref().ok_or(Error::MissingSetting {
+            flag: "--domain-zone".to_string(),
+        })
+    }
+}
+
+impl SiteArgs {
+    fn apply_settings(&mut self, settings: &Settings, matches: &ArgMatches) {
+        apply(
+            &mut self.domain_name,
+            &settings.domain_name,
+            matches,
+            "domain_name",
+        );
+        apply(
+            &mut self.partition,
+            &settings.partition,
+            matches,
+            "partition",
+        );
+        self.domain_zone = self.domain_zone.take().or(settings.domain_zone.clone());
+        self.stack_name = self.stack_name.take().or(settings.stack_name.clone());
+        self.bucket_region = self.bucket_region.take().or(settings.bucket_region.clone());
+        self.profile = self.profile.take().or(settings.profile.clone());
+    }
+
+    ///
+    /// The site's fully qualified domain.
+    ///
+    fn fqdn(&self) -> Result<String, Error> {
+        match &self.domain_zone {
+            Some(domain_zone) => Ok(site_domain(&self.domain_name, domain_zone)),
+            None => Err(Error::MissingSetting {
+                flag: "--domain-zone".to_string(),
+            }),
+        }
+    }
+}
+
+impl PreviewArgs {
+    ///
+    /// Fills in the deploy options from the config file, then drops those a preview mustn't
+    /// inherit from the site it previews. Protection would stop `preview gc` destroying it, and
+    /// aliases - or the buckets and distribution being adopted - belong to the site itself.
+    ///
+    fn apply_settings(&mut self, settings: &Settings, matches: &ArgMatches) -> Result<(), Error> {
+        if given(matches, "protect") && self.deploy.protect {
+            return Err(Error::PreviewOption {
+                flag: "--protect".to_string(),
+            });
+        }
+        self.deploy.apply_settings(settings, matches);
+
+        // Each preview needs stacks of its own, so they aren't named by the config file
+        if !given(matches, "stack_name") {
+            self.deploy.site.stack_name = None;
+        }
+        self.deploy.protect = false;
+        self.deploy.environment = Some("preview".to_string());
+        self.deploy.template = None;
+        self.deploy.adopt_bucket = None;
+        self.deploy.adopt_distribution = None;
+        self.deploy.site.aliases.clear();
+        self.deploy.site.redirect_to_canonical = false;
+        // Previews don't last long enough to need access logs
+        self.deploy.site.no_logging = true;
+        Ok(())
+    }
+}
+
+impl ListArgs {
+    ///
+    /// The regions to look for sites in: those asked for, or the partition's control region.
+    ///
+    fn regions(&self) -> Vec<String> {
+        if self.regions.is_empty() {
+            vec![self.partition.control_region().to_string()]
+        } else {
+            self.regions.clone()
+        }
+    }
+
+    fn apply_settings(&mut self, settings: &Settings, matches: &ArgMatches) {
+        apply(
+            &mut self.partition,
+            &settings.partition,
+            matches,
+            "partition",
+        );
+        self.profile = self.profile.take().or(settings.profile.clone());
+    }
+}
+
+///
+/// Whether an option was given on the command line, rather than left to its default - in which
+/// case the config file gets its say.
+///
+fn given(matches: &ArgMatches, id: &str) -> bool {
+    matches.value_source(id) == Some(ValueSource::CommandLine)
+}
+
+///
+/// Takes an option's value from the config file, unless it was given on the command line. Switches
+/// set in the config file can be turned off with `--switch=false`.
+///
+fn apply<T: Clone>(value: &mut T, setting: &Option<T>, matches: &ArgMatches, id: &str) {
+    if let Some(setting) = setting.as_ref().filter(|_| !given(matches, id)) {
+        *value = setting.clone();
+    }
+}
+
+///
+/// Takes a repeatable option's values from the config file, unless any were given on the command
+/// line.
+///
+fn apply_list<T: Clone>(values: &mut Vec<T>, setting: &Option<Vec<T>>) {
+    if values.is_empty() {
+        *values = setting.clone().unwrap_or_default();
+    }
+}
+
+impl WaitArgs {
+    fn apply_settings(&mut self, settings: &Settings, matches: &ArgMatches) {
+        apply(
+            &mut self.stack_timeout,
+            &settings.stack_timeout,
+            matches,
+            "stack_timeout",
+        );
+        apply(
+            &mut self.poll_interval,
+            &settings.poll_interval,
+            matches,
+            "poll_interval",
+        );
+        apply(
+            &mut self.max_poll_interval,
+            &settings.max_poll_interval,
+            matches,
+            "max_poll_interval",
+        );
+    }
+
+    ///
+    /// Checks we'd back off towards a longer interval, not a shorter one.
+    ///
+    fn validate(&self) -> Result<(), Error> {
+        if self.max_poll_interval < self.poll_interval {
+            return Err(Error::InvalidPollInterval {
+                poll_interval: self.poll_interval,
+                max_poll_interval: self.max_poll_interval,
+            });
+        }
+        Ok(())
+    }
+
+    fn stack_timeout(&self) -> Duration {
+        Duration::from_secs(60 * self.stack_timeout)
+    }
+
+    fn backoff(&self) -> Backoff {
+        Backoff {
+            initial: Duration::from_secs(self.poll_interval),
+            max: Duration::from_secs(self.max_poll_interval),
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Deploy the site, creating it if need be. This is what happens when no command is given.
+    Deploy(Box<DeployArgs>),
+
+    /// Show how a deployed site is doing: its stacks, certificate and distribution, and when it
+    /// was last deployed
+    Status(StatusArgs),
+
+    /// List the sites deployed to the account
+    List(ListArgs),
+
+    /// Have CloudFront fetch some, or all, of a deployed site's files afresh, rather than waiting
+    /// for its caches to expire
+    Invalidate(InvalidateArgs),
+
+    /// Tear down a deployed site: its stacks, buckets, distribution, certificate and DNS records
+    Destroy(DestroyArgs),
+
+    /// Check whether a deployed site's stack has been changed outside the CLI - in the console,
+    /// say - and offer to put it back as the template describes
+    Drift(DriftArgs),
+
+    /// Write out the CloudFormation template, and its parameter values, that deploying the site
+    /// would use - to review it, or to manage the site with other tools
+    Synth(Box<SynthArgs>),
+
+    /// Deploy a short-lived preview of the site - for a pull request, say - at `<id>.<domain
+    /// name>.<zone>`, writing out its URL as JSON
+    Preview(Box<PreviewArgs>),
+
+    /// Work with the config file
+    Config(ConfigArgs),
+}
+
+///
+/// Identifies an already deployed site.
+///
+#[derive(clap::Args, Debug)]
+struct SiteArgs {
+    /// Domain host the site was deployed to. If this isn't specified, the site at the apex is
+    /// used.
+    #[arg(long, default_value = "")]
+    domain_name: String,
+
+    /// Domain zone the site was deployed into
+    #[arg(long)]
+    domain_zone: Option<String>,
+
+    /// The stack name the site was deployed with, if it was given when deploying
     #[arg(long)]
-    deploy: String,
+    stack_name: Option<String>,
+
+    /// The AWS partition the site was deployed into
+    #[arg(long, value_enum, default_value_t = Partition::Aws)]
+    partition: Partition,
+
+    /// The region the site's bucket was deployed to, if it was given when deploying
+    #[arg(long)]
+    bucket_region: Option<String>,
+
+    /// The profile, from the AWS config and credentials files, to use
+    #[arg(long)]
+    profile: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+struct StatusArgs {
+    #[command(flatten)]
+    site: SiteArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct ListArgs {
+    /// The AWS partition to look for sites in
+    #[arg(long, value_enum, default_value_t = Partition::Aws)]
+    partition: Partition,
+
+    /// A region to look for sites in. May be repeated. Without this, only the partition's control
+    /// region is searched, so sites with their bucket elsewhere need their region given.
+    #[arg(long = "region")]
+    regions: Vec<String>,
+
+    /// The profile, from the AWS config and credentials files, to use
+    #[arg(long)]
+    profile: Option<String>,
+}
+
+///
+/// Deploys a preview of the site. The preview takes the same options as a deploy, with
+/// --domain-name naming the domain previews go under rather than the site's own.
+///
+#[derive(clap::Args, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
+struct PreviewArgs {
+    #[command(subcommand)]
+    command: Option<PreviewCommand>,
+
+    /// The preview's ID, such as `pr-123`, which it's served under
+    #[arg(long, required = true)]
+    id: Option<String>,
+
+    /// How long, in hours, the preview lasts before `preview gc` destroys it. Deploying the
+    /// preview again starts the clock afresh. At most a year.
+    #[arg(long, default_value_t = 72, value_parser = clap::value_parser!(u64).range(1..=8760))]
+    ttl_hours: u64,
+
+    #[command(flatten)]
+    deploy: DeployArgs,
+}
+
+#[derive(Subcommand, Debug)]
+enum PreviewCommand {
+    /// Destroy previews that have outlived their time to live
+    Gc(PreviewGcArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct PreviewGcArgs {
+    #[command(flatten)]
+    search: ListArgs,
+
+    /// List the expired previews without destroying them
+    #[arg(long)]
+    dry_run: bool,
+
+    #[command(flatten)]
+    wait: WaitArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct ConfigArgs {
+    #[command(subcommand)]
+    command: ConfigCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Check the config file, and each of its environments, over
+    Validate,
+}
+
+#[derive(clap::Args, Debug)]
+struct InvalidateArgs {
+    #[command(flatten)]
+    site: SiteArgs,
+
+    /// A path to invalidate, such as `/index.html`, or `/blog/*` for everything under a
+    /// directory. May be repeated. Without this, every file is invalidated.
+    #[arg(long = "path")]
+    paths: Vec<String>,
+
+    /// How long to wait, in minutes, for the invalidation to complete
+    #[arg(long, default_value_t = 15)]
+    invalidation_timeout: u64,
+
+    #[command(flatten)]
+    wait: WaitArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct DestroyArgs {
+    #[command(flatten)]
+    site: SiteArgs,
+
+    /// The site's full domain, e.g. `www.example.com`, to confirm the site should be destroyed
+    /// without being asked. Without this, the domain has to be typed in at a prompt.
+    #[arg(long)]
+    confirm: Option<String>,
+
+    /// Remove termination protection from the site's stacks so they can be deleted
+    #[arg(long)]
+    unprotect: bool,
+
+    #[command(flatten)]
+    wait: WaitArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct DriftArgs {
+    #[command(flatten)]
+    site: SiteArgs,
+
+    /// Re-apply the template to any drifted resources that can be repaired, without asking
+    #[arg(long)]
+    repair: bool,
+
+    /// Go ahead with a repair that replaces existing resources, such as the distribution, without
+    /// asking for confirmation
+    #[arg(long)]
+    yes: bool,
+
+    #[command(flatten)]
+    wait: WaitArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct SynthArgs {
+    #[command(flatten)]
+    site: SiteConfigArgs,
+
+    /// Write the template to this file rather than to stdout
+    #[arg(long)]
+    output: Option<String>,
+
+    /// The format to write the template in
+    #[arg(long, value_enum, default_value_t = TemplateFormat::Yaml)]
+    format: TemplateFormat,
+
+    /// Write the parameter values to this file, as JSON that `aws cloudformation create-stack
+    /// --parameters file://...` accepts. Without this, they're logged.
+    #[arg(long)]
+    parameters_output: Option<String>,
+
+    /// Write out the stack holding the site's certificate rather than the site's own stack. Only
+    /// sites whose bucket is outside the partition's control region have one.
+    #[arg(long)]
+    certificate: bool,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum TemplateFormat {
+    Yaml,
+    Json,
 }
 
 #[tokio::main]
 async fn main() -> () {
-    let args: Args = Args::parse();
-    match run(&args).await {
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    match run(args, &matches).await {
         Ok(_) => {
             info!("All done!");
         }
@@ -53,104 +872,237 @@ async fn main() -> () {
     }
 }
 
-async fn run(args: &Args) -> Result<(), Error> {
-    // Setup tracing to write out to the console
-    let subscriber = tracing_subscriber::FmtSubscriber::new();
-    tracing::subscriber::set_global_default(subscriber)?;
+async fn run(mut args: Args, matches: &ArgMatches) -> Result<(), Error> {
+    // Setup tracing to write out to the console. Synth may write the template, and preview its
+    // URL, to stdout, so their logs go to stderr instead.
+    if matches!(
+        args.command,
+        Some(Command::Synth(_)) | Some(Command::Preview(_))
+    ) {
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(std::io::stderr)
+            .finish();
+        tracing::subscriber::set_global_default(subscriber)?;
+    } else {
+        let subscriber = tracing_subscriber::FmtSubscriber::new();
+        tracing::subscriber::set_global_default(subscriber)?;
+    }
+
+    // Fill in anything not given on the command line from the config file
+    let config_file = config::load_config_file(args.config.as_deref())?;
+    if let Some(Command::Config(_)) = &args.command {
+        return validate_config(&config_file);
+    }
+    let settings = config_file.settings(args.env.as_deref())?;
+    let command_matches = match matches.subcommand() {
+        Some((_, command_matches)) => command_matches,
+        None => matches,
+    };
+    match &mut args.command {
+        Some(Command::Deploy(deploy_args)) => {
+            deploy_args.apply_settings(&settings, command_matches)
+        }
+        Some(Command::Status(StatusArgs { site })) => {
+            site.apply_settings(&settings, command_matches)
+        }
+        Some(Command::Invalidate(invalidate_args)) => {
+            invalidate_args
+                .site
+                .apply_settings(&settings, command_matches);
+            invalidate_args
+                .wait
+                .apply_settings(&settings, command_matches);
+            apply(
+                &mut invalidate_args.invalidation_timeout,
+                &settings.invalidation_timeout,
+                command_matches,
+                "invalidation_timeout",
+            );
+        }
+        Some(Command::Destroy(DestroyArgs { site, wait, .. }))
+        | Some(Command::Drift(DriftArgs { site, wait, .. })) => {
+            site.apply_settings(&settings, command_matches);
+            wait.apply_settings(&settings, command_matches);
+        }
+        Some(Command::List(list_args)) => list_args.apply_settings(&settings, command_matches),
+        Some(Command::Synth(synth_args)) => {
+            synth_args.site.apply_settings(&settings, command_matches)
+        }
+        Some(Command::Preview(preview_args)) => match &mut preview_args.command {
+            Some(PreviewCommand::Gc(gc_args)) => {
+                let gc_matches = command_matches
+                    .subcommand_matches("gc")
+                    .expect("clap matched gc");
+                gc_args.search.apply_settings(&settings, gc_matches);
+                gc_args.wait.apply_settings(&settings, gc_matches);
+            }
+            None => preview_args.apply_settings(&settings, command_matches)?,
+        },
+        Some(Command::Config(_)) => {}
+        None => args.deploy.apply_settings(&settings, command_matches),
+    }
+    if let Some(wait) = args.wait_args() {
+        wait.validate()?;
+    }
+
+    match &mut args.command {
+        Some(Command::Deploy(deploy_args)) => deploy(deploy_args).await,
+        Some(Command::Status(status_args)) => status(status_args).await,
+        Some(Command::List(list_args)) => list(list_args).await,
+        Some(Command::Invalidate(invalidate_args)) => invalidate(invalidate_args).await,
+        Some(Command::Destroy(destroy_args)) => destroy(destroy_args).await,
+        Some(Command::Drift(drift_args)) => drift(drift_args).await,
+        Some(Command::Synth(synth_args)) => synth(synth_args).await,
+        Some(Command::Preview(preview_args)) => match &preview_args.command {
+            Some(PreviewCommand::Gc(gc_args)) => preview_gc(gc_args).await,
+            None => preview(preview_args).await,
+        },
+        Some(Command::Config(_)) => validate_config(&config_file),
+        None => deploy(&args.deploy).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // Setup AWS Clients
-    let shared_config = aws_config::from_env().region("us-east-1").load().await;
-    let cfn_client = aws_sdk_cloudformation::Client::new(&shared_config);
-    let r53_client = aws_sdk_route53::Client::new(&shared_config);
-    let sts_client = aws_sdk_sts::Client::new(&shared_config);
-    let s3_client = aws_sdk_s3::Client::new(&shared_config);
-    let cloudfront_client = aws_sdk_cloudfront::Client::new(&shared_config);
+    ///
+    /// Parses a preview command line, and fills it in from the given settings as `run` does.
+    ///
+    fn preview_args(command_line: &[&str], settings: &Settings) -> Result<PreviewArgs, Error> {
+        let matches = Args::command().try_get_matches_from(command_line).unwrap();
+        let command_matches = matches.subcommand_matches("preview").unwrap();
+        let mut args = match Args::from_arg_matches(&matches).unwrap().command {
+            Some(Command::Preview(preview_args)) => *preview_args,
+            command => panic!("expected a preview, got {:?}", command),
+        };
+        args.apply_settings(settings, command_matches)?;
+        Ok(args)
+    }
 
-    // Make sure the directory exists
-    let path = Path::new(&args.deploy);
-    let _ = fs::read_dir(path);
+    ///
+    /// Parses a deploy command line, and fills it in from the given settings as `run` does.
+    ///
+    fn deploy_args(command_line: &[&str], settings: &Settings) -> DeployArgs {
+        let matches = Args::command().try_get_matches_from(command_line).unwrap();
+        let mut args = Args::from_arg_matches(&matches).unwrap().deploy;
+        args.apply_settings(settings, &matches);
+        args
+    }
 
-    // Make sure we've got access
-    info!("Checking AWS access");
-    let _caller_identity = sts_client.get_caller_identity().send().await?;
-    info!("AWS access looks good, continuing");
+    #[test]
+    fn the_command_line_overrides_the_config_file() {
+        let settings = Settings {
+            domain_zone: Some("example.com".to_string()),
+            protect: Some(true),
+            no_logging: Some(true),
+            security_headers: Some(true),
+            stack_timeout: Some(60),
+            poll_interval: Some(10),
+            cache_behaviors: Some(vec!["/api/*=disabled".parse().unwrap()]),
+            ..Settings::default()
+        };
 
-    // Try find the zone ID
-    let zone_id = route53_helpers::find_zone(&args.domain_zone, &r53_client).await?;
-    info!(zone = zone_id, "Found zone");
+        let args = deploy_args(&["staticwebsite"], &settings);
+        assert!(args.protect);
+        assert!(args.site.no_logging);
+        assert!(args.site.security_headers);
+        assert_eq!(args.wait.stack_timeout, 60);
+        assert_eq!(args.wait.poll_interval, 10);
+        assert_eq!(args.site.cache_behaviors.len(), 1);
 
-    // Try find the stack
-    let fqdn = if args.domain_name.eq("") {
-        args.domain_zone.clone()
-    } else {
-        format!("{}.{}", args.domain_name, args.domain_zone)
-    };
+        let args = deploy_args(
+            &[
+                "staticwebsite",
+                "--protect=false",
+                "--no-logging=false",
+                "--security-headers",
+                "--stack-timeout",
+                "30",
+                "--cache-behavior",
+                "/a/*=optimized",
+                "--cache-behavior",
+                "/b/*=optimized",
+            ],
+            &settings,
+        );
+        assert!(!args.protect);
+        assert!(!args.site.no_logging);
+        assert!(args.site.security_headers);
+        assert_eq!(args.wait.stack_timeout, 30);
+        assert_eq!(args.site.cache_behaviors.len(), 2);
+    }
 
-    // If the stack doesn't exist yet, let's deploy it
-    let stack_template = include_str!("cfn_template.yaml").to_string();
-    let stack_name = format!("StaticSite--{}", fqdn.replace(".", "-"));
-    info!(name = &stack_name, "Using Cloudformation stack");
-    if !cloudformation_helpers::stack_exists_and_is_complete(&stack_name, &cfn_client).await? {
-        info!("Stack doesn't exist; creating");
-        let stack_id = cloudformation_helpers::create_stack(
-            &stack_name,
-            &cfn_client,
-            &stack_template,
-            &zone_id,
-            &fqdn,
-        )
-        .await?;
-        info!(stack_id = &stack_id, "Stack created");
-    } else {
-        info!("Stack exists; updating");
-        cloudformation_helpers::update_stack(
-            &stack_name,
-            &cfn_client,
-            &stack_template,
-            &zone_id,
-            &fqdn,
-        )
-        .await?;
-    }
-
-    info!("Waiting for stack deployment to complete");
-    timeout(
-        Duration::from_secs(60 * 15),
-        cloudformation_helpers::wait_for_stack(&stack_name, &cfn_client),
-    )
-    .await??;
-    info!("Stack deploy complete");
-
-    // Upload the site
-    info!("Finding website bucket");
-    let bucket_name = cloudformation_helpers::get_stack_output(
-        &stack_name,
-        &cfn_client,
-        &"StaticWebsiteBucket".to_string(),
-    )
-    .await?;
-    info!(bucket = &bucket_name, "Uploading");
-    upload_directory(&path, &bucket_name, &s3_client).await?;
-
-    // Invalidate the distribution
-    let distribution_id = cloudformation_helpers::get_stack_output(
-        &stack_name,
-        &cfn_client,
-        &"Distribution".to_string(),
-    )
-    .await?;
-    info!(
-        distribution_id = distribution_id,
-        "Invalidating distribution"
-    );
-    let invalidation_id = invalidate_distribution(&distribution_id, &cloudfront_client).await?;
-    timeout(
-        Duration::from_secs(60 * 15),
-        wait_for_invalidation(&invalidation_id, &distribution_id, &cloudfront_client),
-    )
-    .await??;
-
-    info!("Distribution invalidated. Ready to go!");
-    info!(href = format!("https://{}", fqdn), "Link");
-    Ok(())
+    #[test]
+    fn prod_environments_are_protected() {
+        for environment in ["production", "prod"] {
+            let settings = Settings {
+                environment: Some(environment.to_string()),
+                ..Settings::default()
+            };
+            assert_eq!(
+                deploy_args(&["staticwebsite"], &settings).protection(),
+                Some(true)
+            );
+        }
+        let args = deploy_args(
+            &["staticwebsite", "--environment", "staging"],
+            &Settings::default(),
+        );
+        assert_eq!(args.protection(), None);
+    }
+
+    #[test]
+    fn previews_drop_the_sites_own_settings() {
+        let settings = Settings {
+            domain_zone: Some("example.com".to_string()),
+            domain_name: Some("preview".to_string()),
+            stack_name: Some("site".to_string()),
+            aliases: Some(vec!["example.com".to_string()]),
+            deploy: Some("public".to_string()),
+            redirect_to_canonical: Some(true),
+            security_headers: Some(true),
+            environment: Some("production".to_string()),
+            protect: Some(true),
+            ..Settings::default()
+        };
+        let args =
+            preview_args(&["staticwebsite", "preview", "--id", "pr-123"], &settings).unwrap();
+
+        let deploy = &args.deploy;
+        assert_eq!(deploy.site.domain_zone, Some("example.com".to_string()));
+        assert_eq!(deploy.site.domain_name, "preview");
+        assert_eq!(deploy.deploy, Some("public".to_string()));
+        assert!(deploy.site.security_headers);
+        assert_eq!(deploy.site.stack_name, None);
+        assert!(deploy.site.aliases.is_empty());
+        assert!(!deploy.site.redirect_to_canonical);
+        assert!(deploy.site.no_logging);
+        assert_eq!(deploy.environment, Some("preview".to_string()));
+        assert_eq!(deploy.protection(), None);
+    }
+
+    #[test]
+    fn previews_cant_be_protected() {
+        assert!(matches!(
+            preview_args(
+                &["staticwebsite", "preview", "--id", "pr-123", "--protect"],
+                &Settings::default(),
+            ),
+            Err(Error::PreviewOption { .. })
+        ));
+        assert!(Args::command()
+            .try_get_matches_from(["staticwebsite", "preview", "--id", "a", "--ttl-hours", "0"])
+            .is_err());
+        assert!(Args::command()
+            .try_get_matches_from([
+                "staticwebsite",
+                "preview",
+                "--id",
+                "a",
+                "--ttl-hours",
+                "8761"
+            ])
+            .is_err());
+    }
 }
@@ -0,0 +1,70 @@
+use crate::Error;
+use clap::ValueEnum;
+use serde::Deserialize;
+
+///
+/// The AWS partition we're deploying into. Each partition has its own regions and endpoints, so
+/// anything that would otherwise be hard-coded is derived from here.
+///
+/// Only `aws` is offered. CloudFront in AWS China takes IAM server certificates rather than ACM
+/// ones, along with none of the managed cache policies the site's template uses, and CloudFront
+/// isn't available in AWS GovCloud at all.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Partition {
+    Aws,
+}
+
+impl Partition {
+    ///
+    /// The partition's name, as used in ARNs and by `AWS::Partition`.
+    ///
+    pub fn name(&self) -> &'static str {
+        match self {
+            Partition::Aws => "aws",
+        }
+    }
+
+    ///
+    /// The region CloudFront's certificates must be issued in. It's also where we talk to the
+    /// partition's global services - Route53 and CloudFront - from.
+    ///
+    pub fn control_region(&self) -> &'static str {
+        match self {
+            Partition::Aws => "us-east-1",
+        }
+    }
+
+    ///
+    /// Checks we can deploy a site into this partition, with its bucket in `bucket_region`.
+    ///
+    pub fn validate(&self, bucket_region: &str) -> Result<(), Error> {
+        // Regions of the other partitions are named with a prefix of their own
+        if bucket_region.starts_with("cn-") || bucket_region.starts_with("us-gov-") {
+            return Err(Error::UnsupportedPartition {
+                partition: self.name().to_string(),
+                reason: format!("{} isn't one of its regions", bucket_region),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_must_be_in_the_partition() {
+        assert!(Partition::Aws.validate("us-east-1").is_ok());
+        assert!(Partition::Aws.validate("eu-west-2").is_ok());
+        for region in ["cn-north-1", "cn-northwest-1", "us-gov-west-1"] {
+            assert!(matches!(
+                Partition::Aws.validate(region),
+                Err(Error::UnsupportedPartition { .. })
+            ));
+        }
+    }
+}
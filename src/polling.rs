@@ -0,0 +1,51 @@
+use std::time::Duration;
+
+///
+/// How often to poll AWS while waiting on a long-running operation. Polling starts out every
+/// `initial`, so quick operations are noticed promptly, and backs off towards `max` as the wait
+/// drags on.
+///
+#[derive(Clone, Copy, Debug)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            initial: Duration::from_secs(5),
+            max: Duration::from_secs(30),
+        }
+    }
+}
+
+impl Backoff {
+    ///
+    /// Starts a new wait.
+    ///
+    pub fn start(&self) -> Poller {
+        Poller {
+            next: self.initial,
+            max: self.max.max(self.initial),
+        }
+    }
+}
+
+///
+/// Tracks how long to sleep between polls during a single wait.
+///
+pub struct Poller {
+    next: Duration,
+    max: Duration,
+}
+
+impl Poller {
+    ///
+    /// Sleeps until it's time to poll again, then backs off a little further for next time.
+    ///
+    pub async fn wait(&mut self) {
+        tokio::time::sleep(self.next).await;
+        self.next = (self.next * 3 / 2).min(self.max);
+    }
+}
@@ -0,0 +1,160 @@
+use crate::tags::{EXPIRES_TAG, PREVIEW_TAG};
+use crate::Error;
+use aws_sdk_cloudformation::primitives::{DateTime, DateTimeFormat};
+use aws_sdk_cloudformation::types::Tag;
+use std::time::{Duration, SystemTime};
+
+///
+/// A preview of a site - for a pull request, say - deployed alongside it under its own name, and
+/// torn down by `preview gc` once it expires.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Preview {
+    pub id: String,
+    pub domain_name: String,
+    pub expires: SystemTime,
+}
+
+impl Preview {
+    ///
+    /// A preview with the given ID, living under `domain_name` within the zone - `pr-123` under
+    /// `preview` is served from `pr-123.preview.<zone>`. It expires `ttl` from now.
+    ///
+    pub fn new(id: &str, domain_name: &str, ttl: Duration) -> Result<Self, Error> {
+        validate_preview_id(id)?;
+        Ok(Preview {
+            id: id.to_string(),
+            domain_name: if domain_name.is_empty() {
+                id.to_string()
+            } else {
+                format!("{}.{}", id, domain_name)
+            },
+            expires: SystemTime::now() + ttl,
+        })
+    }
+
+    ///
+    /// The tags marking the preview's stacks as a preview, and when it expires.
+    ///
+    pub fn tags(&self) -> Vec<Tag> {
+        vec![
+            Tag::builder().key(PREVIEW_TAG).value(&self.id).build(),
+            Tag::builder()
+                .key(EXPIRES_TAG)
+                .value(format_time(self.expires))
+                .build(),
+        ]
+    }
+
+    ///
+    /// Describes the deployed preview as JSON, for CI to pick the URL out of.
+    ///
+    pub fn to_json(&self, fqdn: &str) -> String {
+        serde_json::json!({
+            "id": &self.id,
+            "domain": fqdn,
+            "url": format!("https://{}", fqdn),
+            "expires": format_time(self.expires),
+        })
+        .to_string()
+    }
+}
+
+///
+/// Reads the time a preview expires from its tag, or None if it can't be read.
+///
+pub fn parse_expiry(value: &str) -> Option<SystemTime> {
+    DateTime::from_str(value, DateTimeFormat::DateTime)
+        .ok()
+        .and_then(|time| SystemTime::try_from(time).ok())
+}
+
+fn format_time(time: SystemTime) -> String {
+    DateTime::from(time)
+        .fmt(DateTimeFormat::DateTime)
+        .expect("times within the next few centuries can be formatted")
+}
+
+///
+/// Checks a preview's ID makes a valid DNS label: up to 63 lowercase letters, digits and hyphens,
+/// not starting or ending with a hyphen.
+///
+fn validate_preview_id(id: &str) -> Result<(), Error> {
+    let reason = if id.is_empty() || id.len() > 63 {
+        Some("it must be between 1 and 63 characters long")
+    } else if !id
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+    {
+        Some("it can only contain lowercase letters, digits and hyphens")
+    } else if id.starts_with('-') || id.ends_with('-') {
+        Some("it can't start or end with a hyphen")
+    } else {
+        None
+    };
+
+    match reason {
+        Some(reason) => Err(Error::InvalidPreviewId {
+            id: id.to_string(),
+            reason: reason.to_string(),
+        }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn previews_live_under_the_domain_name() {
+        let ttl = Duration::from_secs(3600);
+        let preview = Preview::new("pr-123", "preview", ttl).unwrap();
+        assert_eq!(preview.domain_name, "pr-123.preview");
+        assert!(preview.expires > SystemTime::now());
+
+        let preview = Preview::new("pr-123", "", ttl).unwrap();
+        assert_eq!(preview.domain_name, "pr-123");
+
+        for id in ["", "PR-123", "pr_123", "pr.123", "-pr", "pr-"] {
+            assert!(matches!(
+                Preview::new(id, "preview", ttl),
+                Err(Error::InvalidPreviewId { .. })
+            ));
+        }
+        assert!(Preview::new(&"a".repeat(64), "preview", ttl).is_err());
+    }
+
+    #[test]
+    fn expiry_round_trips_through_the_tag() {
+        let preview = Preview::new("pr-123", "preview", Duration::from_secs(3600)).unwrap();
+        let expires = preview
+            .tags()
+            .into_iter()
+            .find(|tag| tag.key() == Some(EXPIRES_TAG))
+            .and_then(|tag| tag.value().map(|value| value.to_string()))
+            .unwrap();
+
+        let parsed = parse_expiry(&expires).unwrap();
+        let difference = preview
+            .expires
+            .duration_since(parsed)
+            .unwrap_or_else(|e| e.duration());
+        assert!(difference < Duration::from_secs(1));
+        assert_eq!(parse_expiry("next tuesday"), None);
+    }
+
+    #[test]
+    fn describes_itself_as_json() {
+        let preview = Preview {
+            id: "pr-123".to_string(),
+            domain_name: "pr-123.preview".to_string(),
+            expires: SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+        };
+        let json: serde_json::Value =
+            serde_json::from_str(&preview.to_json("pr-123.preview.example.com")).unwrap();
+        assert_eq!(json["url"], "https://pr-123.preview.example.com");
+        assert_eq!(json["id"], "pr-123");
+        assert_eq!(json["expires"], "2023-11-14T22:13:20Z");
+    }
+}
@@ -0,0 +1,48 @@
+use crate::Error;
+use std::io::{self, IsTerminal, Write};
+use tracing::warn;
+
+///
+/// Asks a yes/no question on the terminal, returning whether the answer was yes. If there's no
+/// terminal to ask on - when running in CI, for instance - the answer is taken to be no.
+///
+pub fn confirm(question: &str) -> Result<bool, Error> {
+    if !io::stdin().is_terminal() {
+        warn!(
+            question = question,
+            "No terminal to confirm on; pass --yes to go ahead regardless"
+        );
+        return Ok(false);
+    }
+
+    print!("{} [y/N] ", question);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+
+    Ok(matches!(
+        answer.trim().to_ascii_lowercase().as_str(),
+        "y" | "yes"
+    ))
+}
+
+///
+/// Asks for `expected` to be typed back, as a guard against destructive mistakes. Returns whether
+/// it was; as with `confirm`, without a terminal the answer is taken to be no.
+///
+pub fn confirm_by_typing(prompt: &str, expected: &str) -> Result<bool, Error> {
+    if !io::stdin().is_terminal() {
+        warn!(
+            prompt = prompt,
+            "No terminal to confirm on; pass --confirm to go ahead regardless"
+        );
+        return Ok(false);
+    }
+
+    print!("{} ", prompt);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+
+    Ok(answer.trim() == expected)
+}
@@ -0,0 +1,33 @@
+//
+// Viewer request handler redirecting every host other than the
+// canonical one to the same path on the canonical host.
+//
+function handler(event) {
+    var request = event.request;
+    var host = request.headers.host ? request.headers.host.value : '';
+
+    if (host === 'CANONICAL_HOST') {
+        return request;
+    }
+
+    var params = [];
+    for (var key in request.querystring) {
+        var param = request.querystring[key];
+        if (param.multiValue) {
+            param.multiValue.forEach(function (v) {
+                params.push(key + '=' + v.value);
+            });
+        } else {
+            params.push(key + '=' + param.value);
+        }
+    }
+    var query = params.length > 0 ? '?' + params.join('&') : '';
+
+    return {
+        statusCode: 301,
+        statusDescription: 'Moved Permanently',
+        headers: {
+            location: { value: 'https://CANONICAL_HOST' + request.uri + query }
+        }
+    };
+}
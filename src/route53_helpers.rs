@@ -1,28 +1,370 @@
-use aws_sdk_route53::error::SdkError;
-use aws_sdk_route53::operation::list_hosted_zones_by_name::ListHostedZonesByNameError;
+use crate::Error;
+use aws_sdk_route53::types::{Change, ChangeAction, ChangeBatch, ResourceRecordSet, RrType};
+use tracing::{error, info};
 
 ///
-/// Finds the Zone ID for the given zone name.
+/// Finds the Zone ID for the given zone name. Only public zones whose name matches exactly
+/// are considered; if there isn't exactly one such zone, an error is returned.
 ///
 pub async fn find_zone(
-    domain_zone: &String,
+    domain_zone: &str,
     r53_client: &aws_sdk_route53::Client,
-) -> Result<String, SdkError<ListHostedZonesByNameError>> {
-    let zones_response = r53_client
-        .list_hosted_zones_by_name()
-        .dns_name(domain_zone)
+) -> Result<String, Error> {
+    let wanted = normalise_dns_name(domain_zone);
+    let mut matches: Vec<String> = vec![];
+
+    // Zones come back sorted by name, starting from the one we ask for. We page through until
+    // we've walked past any zones bearing the name we're after.
+    let mut next_dns_name = Some(wanted.clone());
+    let mut next_zone_id: Option<String> = None;
+    'pages: while let Some(dns_name) = next_dns_name.take() {
+        let zones_response = r53_client
+            .list_hosted_zones_by_name()
+            .dns_name(dns_name)
+            .set_hosted_zone_id(next_zone_id.take())
+            .send()
+            .await?;
+
+        for zone in zones_response.hosted_zones() {
+            if normalise_dns_name(zone.name()) != wanted {
+                break 'pages;
+            }
+            let private = zone.config().map(|c| c.private_zone()).unwrap_or(false);
+            if !private {
+                matches.push(strip_zone_id_prefix(zone.id()));
+            }
+        }
+
+        if zones_response.is_truncated() {
+            next_dns_name = zones_response.next_dns_name().map(|s| s.to_string());
+            next_zone_id = zones_response.next_hosted_zone_id().map(|s| s.to_string());
+        }
+    }
+
+    match matches.len() {
+        0 => Err(Error::ZoneNotFound {
+            zone: domain_zone.to_string(),
+        }),
+        1 => Ok(matches.remove(0)),
+        _ => Err(Error::AmbiguousZone {
+            zone: domain_zone.to_string(),
+            zone_ids: matches,
+        }),
+    }
+}
+
+///
+/// Finds the most specific public zone that `name` belongs to, by trying `name` itself and then
+/// each of its parent domains in turn. `known_zones` maps zone names we've already resolved to
+/// their IDs, so we don't look them up again.
+///
+pub async fn find_zone_for_name(
+    name: &str,
+    known_zones: &[(String, String)],
+    r53_client: &aws_sdk_route53::Client,
+) -> Result<String, Error> {
+    let mut candidate = name;
+    loop {
+        let known = known_zones
+            .iter()
+            .find(|(zone, _)| normalise_dns_name(zone) == normalise_dns_name(candidate));
+        if let Some((_, zone_id)) = known {
+            return Ok(zone_id.clone());
+        }
+
+        match find_zone(candidate, r53_client).await {
+            Ok(zone_id) => return Ok(zone_id),
+            Err(Error::ZoneNotFound { .. }) => {}
+            Err(e) => return Err(e),
+        }
+
+        match candidate.split_once('.') {
+            Some((_, parent)) if parent.contains('.') => candidate = parent,
+            _ => {
+                return Err(Error::ZoneNotFound { zone: name.to_string() });
+            }
+        }
+    }
+}
+
+///
+/// Checks that an explicitly provided zone ID refers to a public zone with the given name,
+/// returning the bare zone ID.
+///
+pub async fn verify_zone(
+    zone_id: &str,
+    domain_zone: &str,
+    r53_client: &aws_sdk_route53::Client,
+) -> Result<String, Error> {
+    let zone_response = r53_client
+        .get_hosted_zone()
+        .id(strip_zone_id_prefix(zone_id))
         .send()
         .await?;
-    let zone = zones_response
-        .hosted_zones()
-        .first()
-        .expect("A zone with this name exists");
-
-    // Extract the zone ID portion
-    let zone_id_full = zone
-        .id()
-        .to_string();
-    let zone_id = zone_id_full.split("/");
-    let zone_id_parts: Vec<&str> = zone_id.collect();
-    Ok(zone_id_parts[2].to_string())
+    let zone = zone_response
+        .hosted_zone()
+        .expect("GetHostedZone should return the zone");
+
+    let private = zone.config().map(|c| c.private_zone()).unwrap_or(false);
+    if private || normalise_dns_name(zone.name()) != normalise_dns_name(domain_zone) {
+        return Err(Error::ZoneIdMismatch {
+            zone_id: zone_id.to_string(),
+            zone: domain_zone.to_string(),
+        });
+    }
+
+    Ok(strip_zone_id_prefix(zone.id()))
+}
+
+///
+/// A DNS record that's in the way of one the site needs to create.
+///
+#[derive(Clone, Debug)]
+pub struct ConflictingRecord {
+    pub zone_id: String,
+    pub record: ResourceRecordSet,
+}
+
+impl ConflictingRecord {
+    ///
+    /// Whether the record points at `target`, by alias or CNAME.
+    ///
+    pub fn points_at(&self, target: &str) -> bool {
+        let target = normalise_dns_name(target);
+        match self.record.alias_target() {
+            Some(alias_target) => normalise_dns_name(alias_target.dns_name()) == target,
+            None => {
+                self.record.r#type() == &RrType::Cname
+                    && self
+                        .record
+                        .resource_records()
+                        .iter()
+                        .any(|r| normalise_dns_name(r.value()) == target)
+            }
+        }
+    }
+
+    ///
+    /// Describes what the record currently points at.
+    ///
+    pub fn target(&self) -> String {
+        match self.record.alias_target() {
+            Some(alias_target) => format!("alias to {}", alias_target.dns_name()),
+            None => self
+                .record
+                .resource_records()
+                .iter()
+                .map(|r| r.value())
+                .collect::<Vec<&str>>()
+                .join(", "),
+        }
+    }
+}
+
+///
+/// Finds any A, AAAA or CNAME records for `name` in the given zone, other than aliases pointing at
+/// `expected_target` - those are records we've created ourselves on a previous deploy. With
+/// `check_caa`, for a site about to create a CAA record of its own, any CAA record is found too.
+///
+pub async fn find_conflicting_records(
+    name: &str,
+    zone_id: &str,
+    expected_target: Option<&str>,
+    check_caa: bool,
+    r53_client: &aws_sdk_route53::Client,
+) -> Result<Vec<ConflictingRecord>, Error> {
+    let wanted = normalise_dns_name(name);
+    let expected_target = expected_target.map(normalise_dns_name);
+    let mut conflicts: Vec<ConflictingRecord> = vec![];
+
+    let mut next_record: Option<(String, Option<RrType>, Option<String>)> =
+        Some((wanted.clone(), None, None));
+    'pages: while let Some((record_name, record_type, record_identifier)) = next_record.take() {
+        let records_response = r53_client
+            .list_resource_record_sets()
+            .hosted_zone_id(zone_id)
+            .start_record_name(record_name)
+            .set_start_record_type(record_type)
+            .set_start_record_identifier(record_identifier)
+            .send()
+            .await?;
+
+        for record in records_response.resource_record_sets() {
+            if normalise_dns_name(record.name()) != wanted {
+                break 'pages;
+            }
+            if record.r#type() == &RrType::Caa {
+                if check_caa {
+                    conflicts.push(ConflictingRecord {
+                        zone_id: zone_id.to_string(),
+                        record: record.clone(),
+                    });
+                }
+                continue;
+            }
+            if !matches!(record.r#type(), RrType::A | RrType::Aaaa | RrType::Cname) {
+                continue;
+            }
+            let ours = match (record.alias_target(), &expected_target) {
+                (Some(alias_target), Some(expected_target)) => {
+                    normalise_dns_name(alias_target.dns_name()) == *expected_target
+                }
+                _ => false,
+            };
+            if !ours {
+                conflicts.push(ConflictingRecord {
+                    zone_id: zone_id.to_string(),
+                    record: record.clone(),
+                });
+            }
+        }
+
+        if records_response.is_truncated() {
+            next_record = records_response.next_record_name().map(|n| {
+                (
+                    n.to_string(),
+                    records_response.next_record_type().cloned(),
+                    records_response
+                        .next_record_identifier()
+                        .map(|i| i.to_string()),
+                )
+            });
+        }
+    }
+
+    Ok(conflicts)
+}
+
+///
+/// Deletes the given records, so the site's stack can create its own in their place.
+///
+pub async fn delete_records(
+    records: &[ConflictingRecord],
+    r53_client: &aws_sdk_route53::Client,
+) -> Result<(), Error> {
+    for conflict in records {
+        let change = Change::builder()
+            .action(ChangeAction::Delete)
+            .resource_record_set(conflict.record.clone())
+            .build()
+            .expect("a valid record change");
+        let change_batch = ChangeBatch::builder()
+            .comment("Taken over by staticwebsite-cli")
+            .changes(change)
+            .build()
+            .expect("a valid change batch");
+
+        r53_client
+            .change_resource_record_sets()
+            .hosted_zone_id(&conflict.zone_id)
+            .change_batch(change_batch)
+            .send()
+            .await?;
+    }
+
+    Ok(())
+}
+
+///
+/// Puts back records deleted by `delete_records`, when the stack that was to replace them didn't
+/// deploy. Any that can't be put back are logged in full, so they can be recreated by hand.
+///
+pub async fn restore_records(records: &[ConflictingRecord], r53_client: &aws_sdk_route53::Client) {
+    for conflict in records {
+        let change = Change::builder()
+            .action(ChangeAction::Upsert)
+            .resource_record_set(conflict.record.clone())
+            .build()
+            .expect("a valid record change");
+        let change_batch = ChangeBatch::builder()
+            .comment("Restored by staticwebsite-cli")
+            .changes(change)
+            .build()
+            .expect("a valid change batch");
+
+        match r53_client
+            .change_resource_record_sets()
+            .hosted_zone_id(&conflict.zone_id)
+            .change_batch(change_batch)
+            .send()
+            .await
+        {
+            Ok(_) => info!(
+                name = conflict.record.name(),
+                r#type = conflict.record.r#type().as_str(),
+                "Restored DNS record"
+            ),
+            Err(e) => error!(
+                zone_id = &conflict.zone_id,
+                name = conflict.record.name(),
+                r#type = conflict.record.r#type().as_str(),
+                ttl = conflict.record.ttl(),
+                target = conflict.target(),
+                msg = Error::from(e).to_string(),
+                "Couldn't restore DNS record; recreate it by hand"
+            ),
+        }
+    }
+}
+
+///
+/// Lower-cases a DNS name and makes sure it carries a trailing dot, as Route53 reports them.
+///
+fn normalise_dns_name(name: &str) -> String {
+    let name = name.to_ascii_lowercase();
+    if name.ends_with('.') {
+        name
+    } else {
+        format!("{}.", name)
+    }
+}
+
+///
+/// Route53 reports zone IDs as `/hostedzone/ABCDEFG`; we want just the `ABCDEFG` portion.
+///
+fn strip_zone_id_prefix(zone_id: &str) -> String {
+    zone_id
+        .rsplit('/')
+        .next()
+        .expect("split always yields at least one part")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalises_dns_names() {
+        assert_eq!(normalise_dns_name("example.com"), "example.com.");
+        assert_eq!(normalise_dns_name("example.com."), "example.com.");
+        assert_eq!(normalise_dns_name("WWW.Example.COM."), "www.example.com.");
+        assert_eq!(
+            normalise_dns_name("Example.com"),
+            normalise_dns_name("example.COM.")
+        );
+
+        // A zone only matches its own name, not that of a parent or child zone
+        assert_ne!(
+            normalise_dns_name("www.example.com."),
+            normalise_dns_name("example.com")
+        );
+        assert_ne!(
+            normalise_dns_name("example.com"),
+            normalise_dns_name("www.example.com")
+        );
+    }
+
+    #[test]
+    fn strips_zone_id_prefixes() {
+        assert_eq!(
+            strip_zone_id_prefix("/hostedzone/Z0123456789ABC"),
+            "Z0123456789ABC"
+        );
+        assert_eq!(strip_zone_id_prefix("Z0123456789ABC"), "Z0123456789ABC");
+        assert_eq!(
+            strip_zone_id_prefix("/hostedzone/Z0123456789ABC"),
+            strip_zone_id_prefix("Z0123456789ABC")
+        );
+    }
 }
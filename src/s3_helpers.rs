@@ -1,5 +1,8 @@
 use crate::Error;
+use crate::adoption::{stack_from_tags, ExistingBucket};
 use aws_sdk_s3::Client;
+use aws_sdk_s3::error::ProvideErrorMetadata;
+use aws_sdk_s3::types::{Delete, ObjectIdentifier};
 use mime_guess;
 use std::fs;
 use std::path::Path;
@@ -14,27 +17,28 @@ struct UploadTask {
 }
 
 ///
-/// Uploads the contents of the given directory to the given bucket in S3.
+/// Uploads the contents of the given directory to the given bucket in S3, returning the keys
+/// that were uploaded.
 ///
 pub async fn upload_directory(
     directory: &Path,
-    destination_bucket: &String,
+    destination_bucket: &str,
     s3_client: &Client,
-) -> Result<(), Error> {
+) -> Result<Vec<String>, Error> {
     let tasks = directory_to_tasks("".to_string(), directory, destination_bucket)?;
 
     // Upload sequentially for now
-    for task in tasks {
-        upload_file(&task, s3_client).await?;
+    for task in &tasks {
+        upload_file(task, s3_client).await?;
     }
 
-    Ok(())
+    Ok(tasks.into_iter().map(|task| task.destination_path).collect())
 }
 
 fn directory_to_tasks(
     base: String,
     directory: &Path,
-    destination_bucket: &String,
+    destination_bucket: &str,
 ) -> Result<Vec<UploadTask>, Error> {
     let mut ret: Vec<UploadTask> = vec![];
 
@@ -72,7 +76,7 @@ fn directory_to_tasks(
             ret.push(UploadTask {
                 destination_path,
                 source: path.into_boxed_path(),
-                destination_bucket: destination_bucket.clone(),
+                destination_bucket: destination_bucket.to_string(),
             })
         }
     }
@@ -97,3 +101,118 @@ async fn upload_file(task: &UploadTask, s3_client: &Client) -> Result<(), Error>
 
     return Ok(());
 }
+
+///
+/// Deletes everything in the given bucket - every version of every object, and any delete
+/// markers - so the bucket itself can be deleted. A bucket that no longer exists is already as
+/// empty as it's going to get.
+///
+pub async fn empty_bucket(bucket: &str, s3_client: &Client) -> Result<(), Error> {
+    let mut key_marker: Option<String> = None;
+    let mut version_id_marker: Option<String> = None;
+    let mut deleted = 0;
+
+    loop {
+        let versions_response = match s3_client
+            .list_object_versions()
+            .bucket(bucket)
+            .set_key_marker(key_marker.take())
+            .set_version_id_marker(version_id_marker.take())
+            .send()
+            .await
+        {
+            Ok(versions_response) => versions_response,
+            Err(e) if e.code() == Some("NoSuchBucket") => {
+                info!(bucket = bucket, "Bucket is already gone");
+                return Ok(());
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        // Each page holds at most 1000 entries, which is as many as we can delete in one go
+        let mut objects: Vec<ObjectIdentifier> = vec![];
+        for version in versions_response.versions() {
+            objects.push(object_identifier(version.key(), version.version_id()));
+        }
+        for delete_marker in versions_response.delete_markers() {
+            objects.push(object_identifier(delete_marker.key(), delete_marker.version_id()));
+        }
+
+        if !objects.is_empty() {
+            deleted += objects.len();
+            let delete = Delete::builder()
+                .set_objects(Some(objects))
+                .quiet(true)
+                .build()
+                .expect("a valid delete request");
+            let delete_response = s3_client
+                .delete_objects()
+                .bucket(bucket)
+                .delete(delete)
+                .send()
+                .await?;
+
+            if let Some(failure) = delete_response.errors().first() {
+                return Err(Error::EmptyBucketFailed {
+                    bucket: bucket.to_string(),
+                    reason: format!(
+                        "{}: {}",
+                        failure.key().unwrap_or_default(),
+                        failure.message().unwrap_or_default()
+                    ),
+                });
+            }
+        }
+
+        if versions_response.is_truncated().unwrap_or(false) {
+            key_marker = versions_response.next_key_marker().map(|m| m.to_string());
+            version_id_marker = versions_response.next_version_id_marker().map(|m| m.to_string());
+        } else {
+            break;
+        }
+    }
+
+    info!(bucket = bucket, deleted = deleted, "Bucket emptied");
+    Ok(())
+}
+
+fn object_identifier(key: Option<&str>, version_id: Option<&str>) -> ObjectIdentifier {
+    ObjectIdentifier::builder()
+        .set_key(key.map(|k| k.to_string()))
+        .set_version_id(version_id.map(|v| v.to_string()))
+        .build()
+        .expect("listed objects should have a key")
+}
+
+///
+/// Looks up an existing bucket we've been asked to adopt: the region it's in, and the stack
+/// managing it, if any.
+///
+pub async fn existing_bucket(bucket: &str, s3_client: &Client) -> Result<ExistingBucket, Error> {
+    let location = s3_client.get_bucket_location().bucket(bucket).send().await?;
+
+    // Buckets in us-east-1 have no location constraint, and some older ones in eu-west-1 say EU
+    let region = match location.location_constraint().map(|l| l.as_str()) {
+        None | Some("") => "us-east-1".to_string(),
+        Some("EU") => "eu-west-1".to_string(),
+        Some(region) => region.to_string(),
+    };
+
+    // We can only ask about the bucket's tags from its own region; if it's elsewhere, it can't be
+    // adopted anyway
+    let stack_name = if s3_client.config().region().map(|r| r.as_ref()) == Some(region.as_str()) {
+        match s3_client.get_bucket_tagging().bucket(bucket).send().await {
+            Ok(tagging) => stack_from_tags(tagging.tag_set().iter().map(|t| (t.key(), t.value()))),
+            Err(e) if e.code() == Some("NoSuchTagSet") => None,
+            Err(e) => return Err(e.into()),
+        }
+    } else {
+        None
+    };
+
+    Ok(ExistingBucket {
+        name: bucket.to_string(),
+        region,
+        stack_name,
+    })
+}
@@ -0,0 +1,202 @@
+use crate::Error;
+use sha2::{Digest, Sha256};
+
+/// CloudFormation's limit on the length of a stack name
+const MAX_STACK_NAME_LENGTH: usize = 128;
+
+/// How many hex digits of the domain's hash go on the end of a default stack name
+const HASH_LENGTH: usize = 8;
+
+const SITE_PREFIX: &str = "StaticSite--";
+const CERTIFICATE_PREFIX: &str = "StaticSiteCertificate--";
+
+/// Added to a stack name given with `--stack-name` to name the certificate's stack
+const CERTIFICATE_SUFFIX: &str = "-certificate";
+
+///
+/// The names of a site's stacks. The certificate's stack only exists for sites whose bucket is
+/// outside the partition's control region.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct StackNames {
+    pub site: String,
+    pub certificate: String,
+}
+
+impl StackNames {
+    ///
+    /// The names we give a site's stacks by default. The domain is spelled out so the stacks are
+    /// easy to find in the console, and a hash of it added so that domains which only differ in
+    /// where their dots and hyphens are - `a-b.example.com` and `a.b-example.com` - get stacks of
+    /// their own. Long domains are shortened to keep within CloudFormation's limit; the hash
+    /// keeps those apart too.
+    ///
+    pub fn default_for(fqdn: &str) -> Self {
+        StackNames {
+            site: hashed_name(SITE_PREFIX, fqdn),
+            certificate: hashed_name(CERTIFICATE_PREFIX, fqdn),
+        }
+    }
+
+    ///
+    /// The names earlier versions of the CLI gave a site's stacks. These can collide, and can be
+    /// too long for CloudFormation, so are only used to find stacks deployed before the change.
+    ///
+    pub fn legacy_for(fqdn: &str) -> Self {
+        StackNames {
+            site: format!("{}{}", SITE_PREFIX, fqdn.replace('.', "-")),
+            certificate: format!("{}{}", CERTIFICATE_PREFIX, fqdn.replace('.', "-")),
+        }
+    }
+
+    ///
+    /// Names given with `--stack-name`. The certificate's stack, if there is one, is named after
+    /// the site's.
+    ///
+    pub fn custom(stack_name: &str) -> Result<Self, Error> {
+        let names = StackNames {
+            site: stack_name.to_string(),
+            certificate: format!("{}{}", stack_name, CERTIFICATE_SUFFIX),
+        };
+        validate_stack_name(&names.site)?;
+        validate_stack_name(&names.certificate)?;
+        Ok(names)
+    }
+}
+
+///
+/// Checks a name is one CloudFormation accepts for a stack: a letter, followed by letters, digits
+/// and hyphens, and no longer than 128 characters.
+///
+fn validate_stack_name(stack_name: &str) -> Result<(), Error> {
+    let reason = if stack_name.len() > MAX_STACK_NAME_LENGTH {
+        Some(format!(
+            "it can be at most {} characters long",
+            MAX_STACK_NAME_LENGTH
+        ))
+    } else if !stack_name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic())
+    {
+        Some("it must start with a letter".to_string())
+    } else if !stack_name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-')
+    {
+        Some("it can only contain letters, digits and hyphens".to_string())
+    } else {
+        None
+    };
+
+    match reason {
+        Some(reason) => Err(Error::InvalidStackName {
+            stack_name: stack_name.to_string(),
+            reason,
+        }),
+        None => Ok(()),
+    }
+}
+
+///
+/// Whether a stack holds a site, rather than a site's certificate or something else entirely.
+/// Stacks named with `--stack-name` don't have our prefix, so are recognised by the domain tag
+/// we put on them.
+///
+pub fn is_site_stack(stack_name: &str, has_domain_tag: bool) -> bool {
+    if stack_name.starts_with(CERTIFICATE_PREFIX) {
+        false
+    } else if stack_name.starts_with(SITE_PREFIX) {
+        true
+    } else {
+        has_domain_tag && !stack_name.ends_with(CERTIFICATE_SUFFIX)
+    }
+}
+
+fn hashed_name(prefix: &str, fqdn: &str) -> String {
+    let hash: String = Sha256::digest(fqdn.to_lowercase().as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>()[..HASH_LENGTH]
+        .to_string();
+
+    let slug: String = fqdn
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    let room = MAX_STACK_NAME_LENGTH - prefix.len() - HASH_LENGTH - 1;
+    let slug = slug[..slug.len().min(room)].trim_end_matches('-');
+
+    format!("{}{}-{}", prefix, slug, hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_names_spell_out_the_domain() {
+        let names = StackNames::default_for("www.example.com");
+        assert!(names.site.starts_with("StaticSite--www-example-com-"));
+        assert!(names
+            .certificate
+            .starts_with("StaticSiteCertificate--www-example-com-"));
+        assert_eq!(names, StackNames::default_for("www.example.com"));
+        validate_stack_name(&names.site).unwrap();
+        validate_stack_name(&names.certificate).unwrap();
+    }
+
+    #[test]
+    fn default_names_dont_collide() {
+        let a = StackNames::default_for("a-b.example.com");
+        let b = StackNames::default_for("a.b-example.com");
+        assert_ne!(a.site, b.site);
+        assert_ne!(a.certificate, b.certificate);
+
+        // ...which the legacy names did
+        assert_eq!(
+            StackNames::legacy_for("a-b.example.com"),
+            StackNames::legacy_for("a.b-example.com")
+        );
+    }
+
+    #[test]
+    fn long_domains_fit() {
+        let label = "a".repeat(63);
+        let fqdn = format!("{}.{}.{}.example.com", label, label, label);
+        let names = StackNames::default_for(&fqdn);
+        validate_stack_name(&names.site).unwrap();
+        validate_stack_name(&names.certificate).unwrap();
+
+        let other = StackNames::default_for(&format!("b{}", &fqdn[1..]));
+        assert_ne!(names.certificate, other.certificate);
+    }
+
+    #[test]
+    fn custom_names_are_validated() {
+        let names = StackNames::custom("marketing-site").unwrap();
+        assert_eq!(names.certificate, "marketing-site-certificate");
+
+        for name in ["", "1site", "my_site", "my.site"] {
+            assert!(matches!(
+                StackNames::custom(name),
+                Err(Error::InvalidStackName { .. })
+            ));
+        }
+        assert!(StackNames::custom(&"a".repeat(120)).is_err());
+    }
+
+    #[test]
+    fn site_stacks_are_recognised() {
+        let fqdn = "www.example.com";
+        for names in [StackNames::default_for(fqdn), StackNames::legacy_for(fqdn)] {
+            assert!(is_site_stack(&names.site, false));
+            assert!(!is_site_stack(&names.certificate, true));
+        }
+
+        let custom = StackNames::custom("marketing-site").unwrap();
+        assert!(is_site_stack(&custom.site, true));
+        assert!(!is_site_stack(&custom.certificate, true));
+        assert!(!is_site_stack(&custom.site, false));
+    }
+}
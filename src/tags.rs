@@ -0,0 +1,211 @@
+use crate::Error;
+use aws_sdk_cloudformation::types::Tag;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// Tags under this prefix are ours, and set on every deploy
+pub const TAG_PREFIX: &str = "staticwebsite-cli:";
+
+/// Records the domain a stack was deployed for, so that sites can be found whatever their stack
+/// is called
+pub const DOMAIN_TAG: &str = "staticwebsite-cli:domain";
+
+/// Marks a stack as a preview, recording the preview's ID
+pub const PREVIEW_TAG: &str = "staticwebsite-cli:preview";
+
+/// Records when a preview expires, and `preview gc` can destroy it
+pub const EXPIRES_TAG: &str = "staticwebsite-cli:expires";
+
+/// CloudFormation's limit on the number of tags a stack can have
+const MAX_TAGS: usize = 50;
+
+///
+/// A tag to put on the site's stacks, and through them on every resource they create.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct StackTag {
+    pub key: String,
+    pub value: String,
+}
+
+impl FromStr for StackTag {
+    type Err = String;
+
+    ///
+    /// Parses `KEY=VALUE`. The value may be empty, but the key may not.
+    ///
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value) = s
+            .split_once('=')
+            .ok_or("expected KEY=VALUE, e.g. team=web")?;
+        if key.trim().is_empty() {
+            return Err("the tag's key is missing".to_string());
+        }
+        Ok(StackTag {
+            key: key.trim().to_string(),
+            value: value.trim().to_string(),
+        })
+    }
+}
+
+///
+/// Works out the tags for a site's stacks: those from the config file, overridden by any given
+/// on the command line, plus our own recording the CLI's version and the site's domain, and any
+/// others of ours given in `own_tags` - those marking a preview, say. CloudFormation copies stack
+/// tags onto the resources it creates, so these end up on the buckets, distribution and
+/// certificate too.
+///
+pub fn stack_tags(
+    config_tags: &BTreeMap<String, String>,
+    tags: &[StackTag],
+    own_tags: &[Tag],
+    domain_name: &str,
+) -> Result<Vec<Tag>, Error> {
+    let mut merged = config_tags.clone();
+    for tag in tags {
+        merged.insert(tag.key.clone(), tag.value.clone());
+    }
+
+    for (key, value) in &merged {
+        let reason = if key.starts_with(TAG_PREFIX) {
+            Some(format!("keys starting {} are set by the CLI", TAG_PREFIX))
+        } else if key.to_lowercase().starts_with("aws:") {
+            Some("keys starting aws: are reserved by AWS".to_string())
+        } else if key.chars().count() > 128 {
+            Some("keys can be at most 128 characters".to_string())
+        } else if value.chars().count() > 256 {
+            Some("values can be at most 256 characters".to_string())
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            return Err(Error::InvalidTag {
+                key: key.clone(),
+                reason,
+            });
+        }
+    }
+
+    merged.insert(
+        format!("{}version", TAG_PREFIX),
+        env!("CARGO_PKG_VERSION").to_string(),
+    );
+    merged.insert(DOMAIN_TAG.to_string(), domain_name.to_string());
+    for tag in own_tags {
+        if let (Some(key), Some(value)) = (tag.key(), tag.value()) {
+            merged.insert(key.to_string(), value.to_string());
+        }
+    }
+    if merged.len() > MAX_TAGS {
+        return Err(Error::InvalidTag {
+            key: merged.keys().last().cloned().unwrap_or_default(),
+            reason: format!(
+                "a stack can have at most {} tags, including the CLI's own",
+                MAX_TAGS
+            ),
+        });
+    }
+
+    Ok(merged
+        .into_iter()
+        .map(|(key, value)| Tag::builder().key(key).value(value).build())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(key: &str, value: &str) -> StackTag {
+        StackTag {
+            key: key.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    fn pairs(tags: &[Tag]) -> Vec<(String, String)> {
+        tags.iter()
+            .map(|tag| {
+                (
+                    tag.key().unwrap_or_default().to_string(),
+                    tag.value().unwrap_or_default().to_string(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_tags() {
+        assert_eq!("team=web".parse(), Ok(tag("team", "web")));
+        assert_eq!("note=a=b".parse(), Ok(tag("note", "a=b")));
+        assert_eq!("empty=".parse(), Ok(tag("empty", "")));
+        assert!("team".parse::<StackTag>().is_err());
+        assert!("=web".parse::<StackTag>().is_err());
+    }
+
+    #[test]
+    fn command_line_tags_override_the_config_file() {
+        let config_tags = BTreeMap::from([
+            ("team".to_string(), "web".to_string()),
+            ("project".to_string(), "site".to_string()),
+        ]);
+        let tags = stack_tags(
+            &config_tags,
+            &[tag("team", "platform"), tag("environment", "production")],
+            &[],
+            "www.example.com",
+        )
+        .unwrap();
+        assert_eq!(
+            pairs(&tags),
+            vec![
+                ("environment".to_string(), "production".to_string()),
+                ("project".to_string(), "site".to_string()),
+                (
+                    "staticwebsite-cli:domain".to_string(),
+                    "www.example.com".to_string()
+                ),
+                (
+                    "staticwebsite-cli:version".to_string(),
+                    env!("CARGO_PKG_VERSION").to_string()
+                ),
+                ("team".to_string(), "platform".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn reserved_keys_are_rejected() {
+        let domain = "example.com".to_string();
+        for key in ["staticwebsite-cli:domain", "aws:cloudformation:stack-name"] {
+            assert!(matches!(
+                stack_tags(&BTreeMap::new(), &[tag(key, "x")], &[], &domain),
+                Err(Error::InvalidTag { .. })
+            ));
+        }
+        assert!(stack_tags(&BTreeMap::new(), &[tag(&"k".repeat(129), "")], &[], &domain).is_err());
+        assert!(stack_tags(
+            &BTreeMap::new(),
+            &[tag("k", &"v".repeat(257))],
+            &[],
+            &domain
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn our_own_tags_count_towards_the_limit() {
+        let domain = "example.com".to_string();
+        let preview = [Tag::builder().key(PREVIEW_TAG).value("pr-1").build()];
+        let tags = stack_tags(&BTreeMap::new(), &[], &preview, &domain).unwrap();
+        assert!(pairs(&tags).contains(&(PREVIEW_TAG.to_string(), "pr-1".to_string())));
+
+        // 48 of the user's, plus the version, domain and preview tags, is one too many
+        let many: Vec<StackTag> = (0..48).map(|i| tag(&format!("k{}", i), "v")).collect();
+        assert!(stack_tags(&BTreeMap::new(), &many[1..], &preview, &domain).is_ok());
+        assert!(matches!(
+            stack_tags(&BTreeMap::new(), &many, &preview, &domain),
+            Err(Error::InvalidTag { .. })
+        ));
+    }
+}
@@ -0,0 +1,1948 @@
+use crate::template_model::{
+    get_att, join, mapping, push, reference, string, sub, Resource, Template,
+};
+use crate::Error;
+use serde_yaml::Value;
+use std::collections::HashSet;
+use std::str::FromStr;
+
+///
+/// An additional name the website should be served from, and the zone its DNS records
+/// should be created in. The zone is absent if DNS for the alias isn't hosted in Route53.
+///
+#[derive(Clone, Debug)]
+pub struct SiteAlias {
+    pub name: String,
+    pub zone_id: Option<String>,
+}
+
+///
+/// The certificate authorities allowed to issue certificates for the site's names, published as
+/// CAA records. Amazon's own authorities are always allowed, so ACM can issue our certificate.
+///
+#[derive(Clone, Debug, Default)]
+pub struct CaaPolicy {
+    /// Additional authorities allowed to issue certificates, e.g. `letsencrypt.org`
+    pub issuers: Vec<String>,
+
+    /// Where authorities should report policy violations, e.g. `mailto:security@example.com`
+    pub iodef: Option<String>,
+}
+
+/// The authorities ACM issues certificates from
+const AMAZON_CAA_ISSUERS: [&str; 4] = [
+    "amazon.com",
+    "amazontrust.com",
+    "awstrust.com",
+    "amazonaws.com",
+];
+
+impl CaaPolicy {
+    ///
+    /// Checks the issuers and reporting address are things we can publish in a CAA record.
+    ///
+    pub fn validate(&self) -> Result<(), Error> {
+        for issuer in &self.issuers {
+            let valid = !issuer.is_empty()
+                && issuer.contains('.')
+                && issuer
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.');
+            if !valid {
+                return Err(Error::InvalidCaaPolicy {
+                    reason: format!("{} isn't a valid issuer domain", issuer),
+                });
+            }
+        }
+
+        if let Some(iodef) = &self.iodef {
+            let valid = (iodef.starts_with("mailto:") || iodef.starts_with("https://"))
+                && !iodef.contains(|c: char| c == '"' || c.is_whitespace());
+            if !valid {
+                return Err(Error::InvalidCaaPolicy {
+                    reason: format!("{} should be a mailto: or https:// URL", iodef),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// The values of the CAA record implementing this policy.
+    ///
+    fn record_values(&self) -> Vec<String> {
+        let mut issuers: Vec<&str> = AMAZON_CAA_ISSUERS.to_vec();
+        for issuer in &self.issuers {
+            if !issuers.contains(&issuer.as_str()) {
+                issuers.push(issuer);
+            }
+        }
+
+        let mut values: Vec<String> = issuers
+            .iter()
+            .map(|issuer| format!("0 issue \"{}\"", issuer))
+            .collect();
+        if let Some(iodef) = &self.iodef {
+            values.push(format!("0 iodef \"{}\"", iodef));
+        }
+        values
+    }
+}
+
+///
+/// A path on the site that's cached differently to the rest of it - e.g. `/api/*`, with caching
+/// disabled.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct CacheBehavior {
+    pub path_pattern: String,
+    pub cache_policy: CachePolicy,
+}
+
+///
+/// How CloudFront caches the responses for some part of the site.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum CachePolicy {
+    /// CloudFront's managed `CachingOptimized` policy, which the rest of the site uses
+    Optimized,
+
+    /// CloudFront's managed `CachingDisabled` policy
+    Disabled,
+
+    /// A cache policy of your own, by ID
+    Custom(String),
+}
+
+impl CachePolicy {
+    fn id(&self) -> &str {
+        match self {
+            CachePolicy::Optimized => "658327ea-f89d-4fab-a63d-7e88639e58f6",
+            CachePolicy::Disabled => "4135ea2d-6df8-44a3-9df3-4b5a84be39ad",
+            CachePolicy::Custom(id) => id,
+        }
+    }
+}
+
+impl FromStr for CacheBehavior {
+    type Err = String;
+
+    ///
+    /// Parses `PATTERN=POLICY`, where the policy is `optimized`, `disabled` or a cache policy ID.
+    ///
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (path_pattern, cache_policy) = s
+            .split_once('=')
+            .ok_or("expected PATTERN=POLICY, e.g. /api/*=disabled")?;
+        let cache_policy = match cache_policy {
+            "optimized" => CachePolicy::Optimized,
+            "disabled" => CachePolicy::Disabled,
+            "" => return Err("the cache policy is missing".to_string()),
+            id if is_policy_id(id) => CachePolicy::Custom(id.to_string()),
+            other => {
+                return Err(format!(
+                    "{} isn't optimized, disabled or a cache policy ID, such as {}",
+                    other,
+                    CachePolicy::Optimized.id()
+                ))
+            }
+        };
+        Ok(CacheBehavior {
+            path_pattern: path_pattern.to_string(),
+            cache_policy,
+        })
+    }
+}
+
+///
+/// Whether `id` looks like a CloudFront policy ID, which is a UUID.
+///
+fn is_policy_id(id: &str) -> bool {
+    let groups: Vec<&str> = id.split('-').collect();
+    groups.iter().map(|group| group.len()).eq([8, 4, 4, 4, 12])
+        && groups
+            .iter()
+            .all(|group| group.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+///
+/// A page of the site to serve in place of an error, e.g. `/404.html` when a file isn't found.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct ErrorPage {
+    /// The error the page replaces
+    pub error_code: u16,
+
+    pub page_path: String,
+
+    /// The status to serve the page with; the error's own status if absent. Single-page apps
+    /// serve `/index.html` with a 200 in place of a 404, for instance.
+    pub response_code: Option<u16>,
+}
+
+impl FromStr for ErrorPage {
+    type Err = String;
+
+    ///
+    /// Parses `CODE=PATH`, optionally followed by `:STATUS`.
+    ///
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (error_code, page) = s
+            .split_once('=')
+            .ok_or("expected CODE=PATH[:STATUS], e.g. 404=/404.html")?;
+        let error_code = error_code
+            .parse()
+            .map_err(|_| format!("{} isn't an HTTP status code", error_code))?;
+        let (page_path, response_code) = match page.rsplit_once(':') {
+            Some((page_path, status)) => {
+                let status = status
+                    .parse()
+                    .map_err(|_| format!("{} isn't an HTTP status code", status))?;
+                (page_path, Some(status))
+            }
+            None => (page, None),
+        };
+        Ok(ErrorPage {
+            error_code,
+            page_path: page_path.to_string(),
+            response_code,
+        })
+    }
+}
+
+/// The errors CloudFront lets us replace with a page of the site
+const REPLACEABLE_ERROR_CODES: [u16; 11] = [400, 403, 404, 405, 414, 416, 500, 501, 502, 503, 504];
+
+///
+/// Headers to add to every response the site serves.
+///
+#[derive(Clone, Debug, Default)]
+pub struct ResponseHeaders {
+    /// Whether to add HSTS, `X-Content-Type-Options`, `X-Frame-Options`, `Referrer-Policy` and
+    /// `X-XSS-Protection` headers
+    pub security_headers: bool,
+
+    pub custom: Vec<CustomHeader>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CustomHeader {
+    pub name: String,
+    pub value: String,
+}
+
+impl FromStr for CustomHeader {
+    type Err = String;
+
+    ///
+    /// Parses `NAME: VALUE`.
+    ///
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = s
+            .split_once(':')
+            .ok_or("expected NAME: VALUE, e.g. \"Permissions-Policy: camera=()\"")?;
+        Ok(CustomHeader {
+            name: name.trim().to_string(),
+            value: value.trim().to_string(),
+        })
+    }
+}
+
+/// The headers `security_headers` adds, which custom headers can't also set
+const SECURITY_HEADERS: [&str; 5] = [
+    "strict-transport-security",
+    "x-content-type-options",
+    "x-frame-options",
+    "referrer-policy",
+    "x-xss-protection",
+];
+
+///
+/// Where the site's access logs go. When enabled, both CloudFront's and the website bucket's
+/// access logs are kept in a bucket of their own.
+///
+#[derive(Clone, Debug)]
+pub struct LoggingConfig {
+    pub enabled: bool,
+
+    /// How long logs are kept for before being deleted
+    pub retention_days: u32,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            enabled: true,
+            retention_days: 90,
+        }
+    }
+}
+
+///
+/// Everything about a site that shapes the CloudFormation template we deploy for it.
+///
+#[derive(Clone, Debug, Default)]
+pub struct SiteConfig {
+    /// Names other than the primary domain the site should be served from
+    pub aliases: Vec<SiteAlias>,
+
+    /// Whether requests for an alias should be redirected to the primary domain
+    pub redirect_to_canonical: bool,
+
+    /// Whether DNS is hosted outside Route53, in which case we can't create any records and
+    /// the certificate must be validated by hand
+    pub external_dns: bool,
+
+    /// CAA records to publish for each of the site's names
+    pub caa: Option<CaaPolicy>,
+
+    /// Whether the certificate lives in a stack of its own. CloudFront needs certificates in the
+    /// partition's control region, so this is the case when the site's bucket is elsewhere. The
+    /// site's stack then takes the certificate's ARN as a parameter.
+    pub separate_certificate: bool,
+
+    /// Paths cached differently to the rest of the site, in order of precedence
+    pub cache_behaviors: Vec<CacheBehavior>,
+
+    /// Pages to serve in place of errors
+    pub error_pages: Vec<ErrorPage>,
+
+    /// Whether requests over plain HTTP should be redirected to HTTPS, rather than served
+    pub redirect_to_https: bool,
+
+    /// Headers to add to every response
+    pub response_headers: Option<ResponseHeaders>,
+
+    pub logging: LoggingConfig,
+
+    /// The ARN of a WAF web ACL to protect the distribution with. It has to have been created with
+    /// CloudFront scope.
+    pub web_acl_arn: Option<String>,
+
+    /// The name of the website bucket, when it's one we adopted rather than created. Buckets we
+    /// create are named by CloudFormation.
+    pub bucket_name: Option<String>,
+}
+
+impl SiteConfig {
+    ///
+    /// Checks the configuration describes a site we can build a template for.
+    ///
+    pub fn validate(&self) -> Result<(), Error> {
+        let mut logical_ids: HashSet<String> = HashSet::new();
+        for alias in &self.aliases {
+            if !logical_ids.insert(logical_id_for(&alias.name)) {
+                return Err(Error::InvalidAlias {
+                    alias: alias.name.clone(),
+                    reason: "it clashes with another alias".to_string(),
+                });
+            }
+        }
+
+        if let Some(caa) = &self.caa {
+            caa.validate()?;
+        }
+
+        let mut path_patterns: HashSet<&str> = HashSet::new();
+        for behavior in &self.cache_behaviors {
+            let pattern = behavior.path_pattern.as_str();
+            if pattern.is_empty() || pattern == "*" {
+                return Err(invalid(format!(
+                    "{:?} can't be used as a cache behaviour's path pattern",
+                    pattern
+                )));
+            }
+            if !path_patterns.insert(pattern) {
+                return Err(invalid(format!(
+                    "more than one cache behaviour uses {}",
+                    pattern
+                )));
+            }
+        }
+
+        let mut error_codes: HashSet<u16> = HashSet::new();
+        for page in &self.error_pages {
+            if !REPLACEABLE_ERROR_CODES.contains(&page.error_code) {
+                return Err(invalid(format!(
+                    "CloudFront can't serve an error page for {} responses",
+                    page.error_code
+                )));
+            }
+            if let Some(response_code) = page.response_code {
+                if response_code != 200 && !REPLACEABLE_ERROR_CODES.contains(&response_code) {
+                    return Err(invalid(format!(
+                        "CloudFront can't serve an error page with a {} status",
+                        response_code
+                    )));
+                }
+            }
+            if !page.page_path.starts_with('/') {
+                return Err(invalid(format!(
+                    "the error page {} should start with /",
+                    page.page_path
+                )));
+            }
+            if !error_codes.insert(page.error_code) {
+                return Err(invalid(format!(
+                    "more than one error page is given for {} responses",
+                    page.error_code
+                )));
+            }
+        }
+
+        if let Some(headers) = &self.response_headers {
+            if !headers.security_headers && headers.custom.is_empty() {
+                return Err(invalid("no response headers are given".to_string()));
+            }
+            let mut names: HashSet<String> = HashSet::new();
+            for header in &headers.custom {
+                let name = header.name.to_ascii_lowercase();
+                if name.is_empty()
+                    || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                    || header.value.is_empty()
+                {
+                    return Err(invalid(format!(
+                        "{}: {} isn't a valid header",
+                        header.name, header.value
+                    )));
+                }
+                if headers.security_headers && SECURITY_HEADERS.contains(&name.as_str()) {
+                    return Err(invalid(format!(
+                        "the {} header is already set by the security headers",
+                        header.name
+                    )));
+                }
+                if !names.insert(name) {
+                    return Err(invalid(format!(
+                        "the {} header is given more than once",
+                        header.name
+                    )));
+                }
+            }
+        }
+
+        if self.logging.enabled && self.logging.retention_days == 0 {
+            return Err(invalid("logs must be kept for at least a day".to_string()));
+        }
+
+        if let Some(arn) = &self.web_acl_arn {
+            if !arn.starts_with("arn:")
+                || !arn.contains(":wafv2:")
+                || !arn.contains(":global/webacl/")
+            {
+                return Err(invalid(format!(
+                    "{} isn't the ARN of a web ACL with CloudFront scope",
+                    arn
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// Whether requests for aliases get redirected to the primary domain. There's nothing to
+    /// redirect without any aliases.
+    ///
+    fn redirects(&self) -> bool {
+        self.redirect_to_canonical && !self.aliases.is_empty()
+    }
+}
+
+fn invalid(reason: String) -> Error {
+    Error::InvalidSiteConfig { reason }
+}
+
+/// The certificate's logical ID, in both the site and certificate templates
+pub const CERTIFICATE_LOGICAL_ID: &str = "StaticWebsiteCertificate";
+
+const REDIRECT_FUNCTION_LOGICAL_ID: &str = "StaticWebsiteRedirectFunction";
+
+const RESPONSE_HEADERS_POLICY_LOGICAL_ID: &str = "StaticWebsiteResponseHeadersPolicy";
+
+/// The hosted zone that alias records pointing at CloudFront distributions live in
+const CLOUDFRONT_HOSTED_ZONE_ID: &str = "Z2FDTNDATAQYW2";
+
+/// The ID the distribution knows the website bucket by
+const ORIGIN_ID: &str = "WebsiteOrigin";
+
+/// The resources a protected stack won't let be replaced or deleted. Losing any of these takes
+/// the site down, or loses its content or logs.
+pub const PROTECTED_RESOURCES: [&str; 4] = [
+    "CloudfrontDistribution",
+    CERTIFICATE_LOGICAL_ID,
+    "WebsiteBucket",
+    "LoggingBucket",
+];
+
+///
+/// Renders the CloudFormation template for a site served from `domain_name`.
+///
+pub fn render_template(domain_name: &str, config: &SiteConfig) -> Result<Template, Error> {
+    config.validate()?;
+
+    let mut template = Template::default();
+    add_site_parameters(&mut template, config);
+    if config.separate_certificate {
+        template.add_parameter(
+            "CertificateArn",
+            "The ARN of the ACM certificate the distribution serves",
+        );
+    }
+
+    template.add_resource(
+        "WebsiteBucket",
+        website_bucket(config.bucket_name.as_deref(), &config.logging),
+    );
+    template.add_resource("StaticWebsiteBucketPolicy", bucket_policy());
+    if !config.separate_certificate {
+        for (logical_id, resource) in certificate_resources(config) {
+            template.add_resource(&logical_id, resource);
+        }
+    }
+    template.add_resource("StaticWebsiteOAI", origin_access_identity());
+    if config.redirects() {
+        template.add_resource(REDIRECT_FUNCTION_LOGICAL_ID, redirect_function(domain_name));
+    }
+    if let Some(headers) = &config.response_headers {
+        template.add_resource(
+            RESPONSE_HEADERS_POLICY_LOGICAL_ID,
+            response_headers_policy(domain_name, headers),
+        );
+    }
+    template.add_resource("CloudfrontDistribution", distribution(config));
+    if config.logging.enabled {
+        template.add_resource(
+            "LoggingBucket",
+            logging_bucket(config.logging.retention_days),
+        );
+    }
+
+    // Without Route53 we can't create any records; they're printed for the user instead
+    if !config.external_dns {
+        let primary = (
+            String::new(),
+            sub("${DomainName}."),
+            reference("HostedZoneId"),
+        );
+        let aliases = config.aliases.iter().filter_map(|alias| {
+            alias.zone_id.as_ref().map(|zone_id| {
+                (
+                    logical_id_for(&alias.name),
+                    string(&format!("{}.", alias.name)),
+                    string(zone_id),
+                )
+            })
+        });
+        for (suffix, name, zone_id) in std::iter::once(primary).chain(aliases) {
+            template.add_resource(
+                &format!("StaticWebsiteAliasRecord{}", suffix),
+                alias_record("A", name.clone(), zone_id.clone()),
+            );
+            template.add_resource(
+                &format!("StaticWebsiteAliasRecordIPv6{}", suffix),
+                alias_record("AAAA", name, zone_id),
+            );
+        }
+    }
+
+    template.add_output(
+        "StaticWebsiteCfnEndpoint",
+        get_att("CloudfrontDistribution", "DomainName"),
+    );
+    template.add_output("StaticWebsiteBucket", reference("WebsiteBucket"));
+    template.add_output("Distribution", reference("CloudfrontDistribution"));
+
+    Ok(template)
+}
+
+///
+/// Renders the template for the stack holding the site's certificate, and anything the
+/// certificate depends on, when it lives apart from the rest of the site.
+///
+pub fn render_certificate_template(config: &SiteConfig) -> Result<Template, Error> {
+    config.validate()?;
+
+    let mut template = Template::default();
+    add_site_parameters(&mut template, config);
+    for (logical_id, resource) in certificate_resources(config) {
+        template.add_resource(&logical_id, resource);
+    }
+    template.add_output("CertificateArn", reference(CERTIFICATE_LOGICAL_ID));
+
+    Ok(template)
+}
+
+///
+/// The parameters both the site's and the certificate's templates take.
+///
+fn add_site_parameters(template: &mut Template, config: &SiteConfig) {
+    if !config.external_dns {
+        template.add_parameter(
+            "HostedZoneId",
+            "The ID of the hosted zone to deploy our endpoint into",
+        );
+    }
+    template.add_parameter(
+        "DomainName",
+        "The domain, within the zone, to deploy the website to",
+    );
+}
+
+///
+/// Our website bucket. A single older version of each file will be kept, and any older versions
+/// will be deleted permanently after 30 days.
+///
+fn website_bucket(bucket_name: Option<&str>, logging: &LoggingConfig) -> Resource {
+    let mut properties = mapping(vec![
+        ("VersioningConfiguration", versioning()),
+        (
+            "LifecycleConfiguration",
+            mapping(vec![(
+                "Rules",
+                Value::Sequence(vec![mapping(vec![
+                    (
+                        "NoncurrentVersionExpiration",
+                        mapping(vec![
+                            ("NewerNoncurrentVersions", number(1)),
+                            ("NoncurrentDays", number(30)),
+                        ]),
+                    ),
+                    ("Id", string("NoncurrentVersionExpiry")),
+                    ("Status", string("Enabled")),
+                ])]),
+            )]),
+        ),
+        ("BucketEncryption", bucket_encryption()),
+    ]);
+    if let Some(bucket_name) = bucket_name {
+        properties["BucketName"] = string(bucket_name);
+    }
+    if logging.enabled {
+        properties["LoggingConfiguration"] = mapping(vec![
+            ("LogFilePrefix", string("StaticWebsiteBucket/")),
+            ("DestinationBucketName", reference("LoggingBucket")),
+        ]);
+    }
+
+    let mut bucket = Resource::new("AWS::S3::Bucket", properties);
+    bucket.update_replace_policy = Some("Delete".to_string());
+    bucket.deletion_policy = Some("Delete".to_string());
+    bucket
+}
+
+///
+/// Our website bucket policy, letting the distribution read the website's files
+///
+fn bucket_policy() -> Resource {
+    Resource::new(
+        "AWS::S3::BucketPolicy",
+        mapping(vec![
+            ("Bucket", reference("WebsiteBucket")),
+            (
+                "PolicyDocument",
+                mapping(vec![(
+                    "Statement",
+                    Value::Sequence(vec![mapping(vec![
+                        ("Action", string("s3:GetObject")),
+                        ("Effect", string("Allow")),
+                        (
+                            "Principal",
+                            mapping(vec![(
+                                "CanonicalUser",
+                                get_att("StaticWebsiteOAI", "S3CanonicalUserId"),
+                            )]),
+                        ),
+                        (
+                            "Resource",
+                            join("", vec![get_att("WebsiteBucket", "Arn"), string("/*")]),
+                        ),
+                    ])]),
+                )]),
+            ),
+        ]),
+    )
+}
+
+///
+/// Our website's SSL certificate, covering the primary domain and each alias, along with any CAA
+/// records that need to be in place before it's issued.
+///
+fn certificate_resources(config: &SiteConfig) -> Vec<(String, Resource)> {
+    let mut resources: Vec<(String, Resource)> = match &config.caa {
+        Some(caa) if !config.external_dns => caa_records(&config.aliases, caa),
+        _ => vec![],
+    };
+
+    let mut properties = mapping(vec![
+        ("DomainName", reference("DomainName")),
+        ("ValidationMethod", string("DNS")),
+    ]);
+    for alias in &config.aliases {
+        push(
+            &mut properties,
+            "SubjectAlternativeNames",
+            string(&alias.name),
+        );
+    }
+
+    // Without a zone we can't validate the certificate automatically
+    if !config.external_dns {
+        push(
+            &mut properties,
+            "DomainValidationOptions",
+            mapping(vec![
+                ("DomainName", reference("DomainName")),
+                ("HostedZoneId", reference("HostedZoneId")),
+            ]),
+        );
+        for alias in &config.aliases {
+            if let Some(zone_id) = &alias.zone_id {
+                push(
+                    &mut properties,
+                    "DomainValidationOptions",
+                    mapping(vec![
+                        ("DomainName", string(&alias.name)),
+                        ("HostedZoneId", string(zone_id)),
+                    ]),
+                );
+            }
+        }
+    }
+
+    let mut certificate = Resource::new("AWS::CertificateManager::Certificate", properties);
+    certificate.depends_on = resources.iter().map(|(id, _)| id.clone()).collect();
+    resources.push((CERTIFICATE_LOGICAL_ID.to_string(), certificate));
+    resources
+}
+
+///
+/// A CAA record for the primary domain and each alias. The certificate depends on the records,
+/// so they're in place before ACM checks them when issuing it.
+///
+fn caa_records(aliases: &[SiteAlias], caa: &CaaPolicy) -> Vec<(String, Resource)> {
+    let values: Vec<Value> = caa.record_values().iter().map(|v| string(v)).collect();
+    let record = |name: Value, zone_id: Value| {
+        Resource::new(
+            "AWS::Route53::RecordSet",
+            mapping(vec![
+                ("Name", name),
+                ("Type", string("CAA")),
+                ("TTL", string("300")),
+                ("ResourceRecords", Value::Sequence(values.clone())),
+                ("HostedZoneId", zone_id),
+            ]),
+        )
+    };
+
+    let mut records = vec![(
+        caa_record_logical_id(None),
+        record(sub("${DomainName}."), reference("HostedZoneId")),
+    )];
+    for alias in aliases {
+        if let Some(zone_id) = &alias.zone_id {
+            records.push((
+                caa_record_logical_id(Some(&alias.name)),
+                record(string(&format!("{}.", alias.name)), string(zone_id)),
+            ));
+        }
+    }
+    records
+}
+
+///
+/// The logical ID of the CAA record for an alias, or for the primary domain without one.
+///
+pub fn caa_record_logical_id(alias: Option<&str>) -> String {
+    match alias {
+        Some(alias) => format!("StaticWebsiteCaaRecord{}", logical_id_for(alias)),
+        None => "StaticWebsiteCaaRecord".to_string(),
+    }
+}
+
+///
+/// An OAI granting access from our distribution to the static website bucket
+///
+fn origin_access_identity() -> Resource {
+    Resource::new(
+        "AWS::CloudFront::CloudFrontOriginAccessIdentity",
+        mapping(vec![(
+            "CloudFrontOriginAccessIdentityConfig",
+            mapping(vec![("Comment", string("Identity for make-me-static"))]),
+        )]),
+    )
+}
+
+///
+/// Our website's CloudFront distribution
+///
+fn distribution(config: &SiteConfig) -> Resource {
+    let certificate_arn = if config.separate_certificate {
+        reference("CertificateArn")
+    } else {
+        reference(CERTIFICATE_LOGICAL_ID)
+    };
+
+    let mut distribution_config = mapping(vec![
+        (
+            "Aliases",
+            Value::Sequence(
+                std::iter::once(reference("DomainName"))
+                    .chain(config.aliases.iter().map(|alias| string(&alias.name)))
+                    .collect(),
+            ),
+        ),
+        (
+            "DefaultCacheBehavior",
+            cache_behavior(None, &CachePolicy::Optimized, config),
+        ),
+        ("DefaultRootObject", string("index.html")),
+        ("Enabled", Value::Bool(true)),
+        ("HttpVersion", string("http2")),
+        ("IPV6Enabled", Value::Bool(true)),
+        (
+            "Origins",
+            Value::Sequence(vec![mapping(vec![
+                ("DomainName", get_att("WebsiteBucket", "RegionalDomainName")),
+                ("Id", string(ORIGIN_ID)),
+                (
+                    "S3OriginConfig",
+                    mapping(vec![(
+                        "OriginAccessIdentity",
+                        join(
+                            "",
+                            vec![
+                                string("origin-access-identity/cloudfront/"),
+                                reference("StaticWebsiteOAI"),
+                            ],
+                        ),
+                    )]),
+                ),
+            ])]),
+        ),
+        (
+            "ViewerCertificate",
+            mapping(vec![
+                ("AcmCertificateArn", certificate_arn),
+                ("MinimumProtocolVersion", string("TLSv1.2_2019")),
+                ("SslSupportMethod", string("sni-only")),
+            ]),
+        ),
+    ]);
+
+    for behavior in &config.cache_behaviors {
+        push(
+            &mut distribution_config,
+            "CacheBehaviors",
+            cache_behavior(Some(&behavior.path_pattern), &behavior.cache_policy, config),
+        );
+    }
+
+    for page in &config.error_pages {
+        push(
+            &mut distribution_config,
+            "CustomErrorResponses",
+            mapping(vec![
+                ("ErrorCode", number(page.error_code.into())),
+                ("ResponsePagePath", string(&page.page_path)),
+                (
+                    "ResponseCode",
+                    number(page.response_code.unwrap_or(page.error_code).into()),
+                ),
+            ]),
+        );
+    }
+
+    if config.logging.enabled {
+        distribution_config["Logging"] = mapping(vec![
+            ("Prefix", string("Cloudfront")),
+            ("Bucket", get_att("LoggingBucket", "RegionalDomainName")),
+            ("IncludeCookies", Value::Bool(false)),
+        ]);
+    }
+
+    if let Some(arn) = &config.web_acl_arn {
+        distribution_config["WebACLId"] = string(arn);
+    }
+
+    Resource::new(
+        "AWS::CloudFront::Distribution",
+        mapping(vec![("DistributionConfig", distribution_config)]),
+    )
+}
+
+///
+/// How the distribution serves requests matching `path_pattern`, or every other request if
+/// there's no pattern. The redirects and response headers apply whatever the path.
+///
+fn cache_behavior(
+    path_pattern: Option<&str>,
+    cache_policy: &CachePolicy,
+    config: &SiteConfig,
+) -> Value {
+    let mut behavior = mapping(vec![
+        ("CachePolicyId", string(cache_policy.id())),
+        ("Compress", Value::Bool(true)),
+        ("TargetOriginId", string(ORIGIN_ID)),
+        (
+            "ViewerProtocolPolicy",
+            string(if config.redirect_to_https {
+                "redirect-to-https"
+            } else {
+                "allow-all"
+            }),
+        ),
+    ]);
+    if let Some(path_pattern) = path_pattern {
+        behavior["PathPattern"] = string(path_pattern);
+    }
+    if config.redirects() {
+        push(
+            &mut behavior,
+            "FunctionAssociations",
+            mapping(vec![
+                ("EventType", string("viewer-request")),
+                (
+                    "FunctionARN",
+                    get_att(REDIRECT_FUNCTION_LOGICAL_ID, "FunctionARN"),
+                ),
+            ]),
+        );
+    }
+    if config.response_headers.is_some() {
+        behavior["ResponseHeadersPolicyId"] = reference(RESPONSE_HEADERS_POLICY_LOGICAL_ID);
+    }
+    behavior
+}
+
+///
+/// A CloudFront Function which permanently redirects requests for any host other than
+/// `canonical_name` to the same path on `canonical_name`.
+///
+fn redirect_function(canonical_name: &str) -> Resource {
+    Resource::new(
+        "AWS::CloudFront::Function",
+        mapping(vec![
+            (
+                "Name",
+                string(&resource_name("StaticSite-redirect", canonical_name, 64)),
+            ),
+            ("AutoPublish", Value::Bool(true)),
+            (
+                "FunctionConfig",
+                mapping(vec![
+                    (
+                        "Comment",
+                        string(&format!("Redirects aliases to {}", canonical_name)),
+                    ),
+                    ("Runtime", string("cloudfront-js-2.0")),
+                ]),
+            ),
+            (
+                "FunctionCode",
+                string(
+                    &include_str!("redirect_function.js").replace("CANONICAL_HOST", canonical_name),
+                ),
+            ),
+        ]),
+    )
+}
+
+///
+/// The headers added to every response the site serves.
+///
+fn response_headers_policy(domain_name: &str, headers: &ResponseHeaders) -> Resource {
+    let mut policy_config = mapping(vec![
+        (
+            "Name",
+            string(&resource_name("StaticSite-headers", domain_name, 128)),
+        ),
+        (
+            "Comment",
+            string(&format!("Response headers for {}", domain_name)),
+        ),
+    ]);
+
+    if headers.security_headers {
+        policy_config["SecurityHeadersConfig"] = mapping(vec![
+            (
+                "StrictTransportSecurity",
+                mapping(vec![
+                    ("AccessControlMaxAgeSec", number(63072000)),
+                    ("Override", Value::Bool(true)),
+                ]),
+            ),
+            (
+                "ContentTypeOptions",
+                mapping(vec![("Override", Value::Bool(true))]),
+            ),
+            (
+                "FrameOptions",
+                mapping(vec![
+                    ("FrameOption", string("SAMEORIGIN")),
+                    ("Override", Value::Bool(true)),
+                ]),
+            ),
+            (
+                "ReferrerPolicy",
+                mapping(vec![
+                    ("ReferrerPolicy", string("strict-origin-when-cross-origin")),
+                    ("Override", Value::Bool(true)),
+                ]),
+            ),
+            (
+                "XSSProtection",
+                mapping(vec![
+                    ("Protection", Value::Bool(true)),
+                    ("ModeBlock", Value::Bool(true)),
+                    ("Override", Value::Bool(true)),
+                ]),
+            ),
+        ]);
+    }
+
+    if !headers.custom.is_empty() {
+        policy_config["CustomHeadersConfig"] = mapping(vec![(
+            "Items",
+            Value::Sequence(
+                headers
+                    .custom
+                    .iter()
+                    .map(|header| {
+                        mapping(vec![
+                            ("Header", string(&header.name)),
+                            ("Value", string(&header.value)),
+                            ("Override", Value::Bool(true)),
+                        ])
+                    })
+                    .collect(),
+            ),
+        )]);
+    }
+
+    Resource::new(
+        "AWS::CloudFront::ResponseHeadersPolicy",
+        mapping(vec![("ResponseHeadersPolicyConfig", policy_config)]),
+    )
+}
+
+///
+/// The access logging bucket for our CloudFront distribution and website bucket. Logs will be
+/// cleaned up after `retention_days` and non-current versions will be cleaned up after 30.
+///
+fn logging_bucket(retention_days: u32) -> Resource {
+    let mut bucket = Resource::new(
+        "AWS::S3::Bucket",
+        mapping(vec![
+            ("AccessControl", string("LogDeliveryWrite")),
+            ("VersioningConfiguration", versioning()),
+            (
+                "LifecycleConfiguration",
+                mapping(vec![(
+                    "Rules",
+                    Value::Sequence(vec![
+                        mapping(vec![
+                            ("ExpirationInDays", number(retention_days.into())),
+                            ("Status", string("Enabled")),
+                            (
+                                "Id",
+                                string(&format!("ExpireLogsAfter{}Days", retention_days)),
+                            ),
+                        ]),
+                        mapping(vec![
+                            (
+                                "NoncurrentVersionExpiration",
+                                mapping(vec![("NoncurrentDays", number(30))]),
+                            ),
+                            ("Status", string("Enabled")),
+                            ("Id", string("ExpireNoncurrentVersions")),
+                        ]),
+                    ]),
+                )]),
+            ),
+            ("BucketEncryption", bucket_encryption()),
+            (
+                "OwnershipControls",
+                mapping(vec![(
+                    "Rules",
+                    Value::Sequence(vec![mapping(vec![(
+                        "ObjectOwnership",
+                        string("BucketOwnerPreferred"),
+                    )])]),
+                )]),
+            ),
+        ]),
+    );
+    bucket.deletion_policy = Some("Delete".to_string());
+    bucket
+}
+
+///
+/// A DNS record pointing `name` at the distribution. The distribution has IPv6 enabled, so each
+/// name gets both an `A` and an `AAAA` record.
+///
+fn alias_record(record_type: &str, name: Value, zone_id: Value) -> Resource {
+    Resource::new(
+        "AWS::Route53::RecordSet",
+        mapping(vec![
+            ("Name", name),
+            ("Type", string(record_type)),
+            (
+                "AliasTarget",
+                mapping(vec![
+                    ("DNSName", get_att("CloudfrontDistribution", "DomainName")),
+                    ("HostedZoneId", string(CLOUDFRONT_HOSTED_ZONE_ID)),
+                ]),
+            ),
+            ("HostedZoneId", zone_id),
+        ]),
+    )
+}
+
+fn versioning() -> Value {
+    mapping(vec![("Status", string("Enabled"))])
+}
+
+fn bucket_encryption() -> Value {
+    mapping(vec![(
+        "ServerSideEncryptionConfiguration",
+        Value::Sequence(vec![mapping(vec![(
+            "ServerSideEncryptionByDefault",
+            mapping(vec![("SSEAlgorithm", string("AES256"))]),
+        )])]),
+    )])
+}
+
+fn number(value: u64) -> Value {
+    Value::Number(value.into())
+}
+
+///
+/// A name for an account-wide resource belonging to the site, e.g.
+/// `StaticSite-redirect-www-example-com`, cut down to `max_length`.
+///
+fn resource_name(prefix: &str, domain_name: &str, max_length: usize) -> String {
+    format!("{}-{}", prefix, domain_name.replace('.', "-"))
+        .chars()
+        .take(max_length)
+        .collect()
+}
+
+///
+/// Checks that an alias looks like a host name we can serve the site from.
+///
+pub fn validate_alias(alias: &str, domain_name: &str) -> Result<(), Error> {
+    let reason = if alias.eq_ignore_ascii_case(domain_name) {
+        Some("it is the same as the primary domain")
+    } else if alias.is_empty()
+        || alias.starts_with('.')
+        || alias.ends_with('.')
+        || alias.contains("..")
+        || !alias
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+    {
+        Some("it isn't a valid host name")
+    } else {
+        None
+    };
+
+    match reason {
+        Some(reason) => Err(Error::InvalidAlias {
+            alias: alias.to_string(),
+            reason: reason.to_string(),
+        }),
+        None => Ok(()),
+    }
+}
+
+///
+/// Renders the stack policy for a site's stacks. A protected stack's updates can't replace or
+/// delete any of the `PROTECTED_RESOURCES`; otherwise anything goes. A stack policy can't be
+/// removed once set, only replaced, so the unprotected policy is used to lift protection.
+///
+pub fn stack_policy(protect: bool) -> String {
+    let mut statements = vec![serde_json::json!({
+        "Effect": "Allow",
+        "Action": "Update:*",
+        "Principal": "*",
+        "Resource": "*",
+    })];
+    if protect {
+        statements.push(serde_json::json!({
+            "Effect": "Deny",
+            "Action": ["Update:Replace", "Update:Delete"],
+            "Principal": "*",
+            "Resource": PROTECTED_RESOURCES
+                .iter()
+                .map(|logical_id| format!("LogicalResourceId/{}", logical_id))
+                .collect::<Vec<String>>(),
+        }));
+    }
+    serde_json::json!({ "Statement": statements }).to_string()
+}
+
+///
+/// Finds the name of an adopted website bucket in a deployed template. The bucket has to keep
+/// being given that name, or CloudFormation would replace it with a new one.
+///
+pub fn adopted_bucket_name(template_body: &str) -> Result<Option<String>, Error> {
+    let template: Value = serde_yaml::from_str(template_body)?;
+    Ok(
+        template["Resources"]["WebsiteBucket"]["Properties"]["BucketName"]
+            .as_str()
+            .map(|name| name.to_string()),
+    )
+}
+
+///
+/// Makes a harmless change to each of the given resources in a deployed template, so that
+/// deploying it again makes CloudFormation update them, re-applying the configuration the template
+/// describes over any changes made outside CloudFormation. Only resources whose every setting is
+/// sent afresh when they're updated can be repaired this way: changing a bucket's tags, say,
+/// wouldn't put back its encryption. Returns the stamped template, along with the resources that
+/// can't be repaired.
+///
+pub fn stamp_for_repair(
+    template_body: &str,
+    resources: &[String],
+    stamp: &str,
+) -> Result<(String, Vec<String>), Error> {
+    let mut template: Value = serde_yaml::from_str(template_body)?;
+    let mut unrepairable: Vec<String> = vec![];
+
+    for resource_id in resources {
+        let resource = &mut template["Resources"][resource_id.as_str()];
+        let resource_type = resource["Type"].as_str().map(|t| t.to_string());
+        let properties = &mut resource["Properties"];
+        match resource_type.as_deref() {
+            Some("AWS::CloudFront::Distribution") => {
+                properties["DistributionConfig"]["Comment"] =
+                    Value::String(format!("Repaired by staticwebsite-cli at {}", stamp));
+            }
+            Some("AWS::CloudFront::CloudFrontOriginAccessIdentity") => {
+                properties["CloudFrontOriginAccessIdentityConfig"]["Comment"] =
+                    Value::String(format!("Repaired by staticwebsite-cli at {}", stamp));
+            }
+            Some("AWS::S3::BucketPolicy") => {
+                properties["PolicyDocument"]["Id"] = Value::String(format!("repaired-{}", stamp));
+            }
+            _ => unrepairable.push(resource_id.clone()),
+        }
+    }
+
+    Ok((
+        serde_yaml::to_string(&template).expect("a YAML value should serialize"),
+        unrepairable,
+    ))
+}
+
+///
+/// Turns a host name into something usable within a CloudFormation logical ID - e.g.
+/// `www.example-site.com` becomes `WwwExamplesiteCom`.
+///
+fn logical_id_for(name: &str) -> String {
+    name.split('.')
+        .map(|label| {
+            let label: String = label
+                .chars()
+                .filter(|c| c.is_ascii_alphanumeric())
+                .collect();
+            let mut chars = label.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn domain() -> String {
+        "example.com".to_string()
+    }
+
+    fn alias(name: &str, zone_id: Option<&str>) -> SiteAlias {
+        SiteAlias {
+            name: name.to_string(),
+            zone_id: zone_id.map(|zone_id| zone_id.to_string()),
+        }
+    }
+
+    /// Renders the site's template and parses it back, so we assert on what CloudFormation gets
+    fn render(config: &SiteConfig) -> Value {
+        serde_yaml::from_str(&render_template(&domain(), config).unwrap().to_yaml()).unwrap()
+    }
+
+    fn resource_ids(template: &Value) -> Vec<String> {
+        template["Resources"]
+            .as_mapping()
+            .unwrap()
+            .keys()
+            .map(|key| key.as_str().unwrap().to_string())
+            .collect()
+    }
+
+    fn distribution_config(template: &Value) -> &Value {
+        &template["Resources"]["CloudfrontDistribution"]["Properties"]["DistributionConfig"]
+    }
+
+    fn reference_to(value: &Value) -> Option<&str> {
+        match value {
+            Value::Tagged(tagged) if tagged.tag == "Ref" => tagged.value.as_str(),
+            _ => None,
+        }
+    }
+
+    ///
+    /// Every logical ID the template refers to through `!Ref` or `!GetAtt`.
+    ///
+    fn referenced_ids(value: &Value, ids: &mut Vec<String>) {
+        match value {
+            Value::Tagged(tagged) if tagged.tag == "Ref" => {
+                ids.push(tagged.value.as_str().unwrap().to_string())
+            }
+            Value::Tagged(tagged) if tagged.tag == "GetAtt" => {
+                let target = tagged.value.as_str().unwrap();
+                ids.push(target.split('.').next().unwrap().to_string())
+            }
+            Value::Tagged(tagged) => referenced_ids(&tagged.value, ids),
+            Value::Sequence(values) => values.iter().for_each(|v| referenced_ids(v, ids)),
+            Value::Mapping(mapping) => mapping.values().for_each(|v| referenced_ids(v, ids)),
+            _ => {}
+        }
+    }
+
+    fn assert_references_resolve(template: &Value) {
+        let mut ids = vec![];
+        referenced_ids(template, &mut ids);
+        for id in ids {
+            let declared = template["Resources"].get(&id).is_some()
+                || template["Parameters"].get(&id).is_some()
+                || id.starts_with("AWS::");
+            assert!(declared, "{} is referenced but not declared", id);
+        }
+    }
+
+    fn full_config() -> SiteConfig {
+        SiteConfig {
+            aliases: vec![
+                alias("www.example.com", Some("Z1")),
+                alias("example.net", None),
+            ],
+            redirect_to_canonical: true,
+            caa: Some(CaaPolicy::default()),
+            cache_behaviors: vec!["/api/*=disabled".parse().unwrap()],
+            error_pages: vec!["404=/index.html:200".parse().unwrap()],
+            response_headers: Some(ResponseHeaders {
+                security_headers: true,
+                custom: vec!["Permissions-Policy: camera=()".parse().unwrap()],
+            }),
+            web_acl_arn: Some(
+                "arn:aws:wafv2:us-east-1:123456789012:global/webacl/site/abc".to_string(),
+            ),
+            ..SiteConfig::default()
+        }
+    }
+
+    #[test]
+    fn default_site() {
+        let template = render(&SiteConfig::default());
+
+        assert_eq!(
+            resource_ids(&template),
+            vec![
+                "WebsiteBucket",
+                "StaticWebsiteBucketPolicy",
+                "StaticWebsiteCertificate",
+                "StaticWebsiteOAI",
+                "CloudfrontDistribution",
+                "LoggingBucket",
+                "StaticWebsiteAliasRecord",
+                "StaticWebsiteAliasRecordIPv6",
+            ]
+        );
+        for output in [
+            "StaticWebsiteCfnEndpoint",
+            "StaticWebsiteBucket",
+            "Distribution",
+        ] {
+            assert!(
+                matches!(template["Outputs"][output]["Value"], Value::Tagged(_)),
+                "{}",
+                output
+            );
+        }
+        assert!(template["Parameters"]["HostedZoneId"].is_mapping());
+        assert!(template["Parameters"]["DomainName"].is_mapping());
+
+        let config = distribution_config(&template);
+        assert_eq!(
+            config["DefaultCacheBehavior"]["CachePolicyId"],
+            CachePolicy::Optimized.id()
+        );
+        assert!(config.get("CacheBehaviors").is_none());
+        assert!(config.get("CustomErrorResponses").is_none());
+        assert!(config.get("WebACLId").is_none());
+        assert_eq!(
+            reference_to(&config["ViewerCertificate"]["AcmCertificateArn"]),
+            Some(CERTIFICATE_LOGICAL_ID)
+        );
+        assert_eq!(
+            template["Resources"]["LoggingBucket"]["Properties"]["LifecycleConfiguration"]["Rules"]
+                [0]["ExpirationInDays"],
+            90
+        );
+        assert_references_resolve(&template);
+    }
+
+    #[test]
+    fn every_feature_renders_a_consistent_template() {
+        let template = render(&full_config());
+        assert_references_resolve(&template);
+
+        let mut config = full_config();
+        config.separate_certificate = true;
+        assert_references_resolve(&render(&config));
+
+        config.external_dns = true;
+        assert_references_resolve(&render(&config));
+    }
+
+    #[test]
+    fn stack_policy_protects_resources_the_template_has() {
+        let template = render(&full_config());
+        let resources = resource_ids(&template);
+        for logical_id in PROTECTED_RESOURCES {
+            assert!(
+                resources.contains(&logical_id.to_string()),
+                "{}",
+                logical_id
+            );
+        }
+
+        let policy: serde_json::Value = serde_json::from_str(&stack_policy(true)).unwrap();
+        let deny = &policy["Statement"][1];
+        assert_eq!(deny["Effect"], "Deny");
+        assert_eq!(
+            deny["Resource"][0],
+            "LogicalResourceId/CloudfrontDistribution"
+        );
+
+        let policy: serde_json::Value = serde_json::from_str(&stack_policy(false)).unwrap();
+        assert_eq!(policy["Statement"].as_array().unwrap().len(), 1);
+        assert_eq!(policy["Statement"][0]["Effect"], "Allow");
+    }
+
+    #[test]
+    fn adopted_buckets_keep_their_name() {
+        let template = render(&SiteConfig::default());
+        assert!(template["Resources"]["WebsiteBucket"]["Properties"]
+            .get("BucketName")
+            .is_none());
+        let deployed = render_template(&domain(), &SiteConfig::default())
+            .unwrap()
+            .to_yaml();
+        assert_eq!(adopted_bucket_name(&deployed).unwrap(), None);
+
+        let config = SiteConfig {
+            bucket_name: Some("example-site".to_string()),
+            ..SiteConfig::default()
+        };
+        let deployed = render_template(&domain(), &config).unwrap();
+        assert_eq!(
+            adopted_bucket_name(&deployed.to_yaml()).unwrap(),
+            Some("example-site".to_string())
+        );
+        assert_eq!(
+            adopted_bucket_name(&deployed.to_json()).unwrap(),
+            Some("example-site".to_string())
+        );
+    }
+
+    #[test]
+    fn aliases() {
+        let template = render(&SiteConfig {
+            aliases: vec![
+                alias("www.example.com", Some("Z1")),
+                alias("example.net", None),
+            ],
+            ..SiteConfig::default()
+        });
+
+        let certificate = &template["Resources"][CERTIFICATE_LOGICAL_ID]["Properties"];
+        assert_eq!(
+            certificate["SubjectAlternativeNames"],
+            serde_yaml::from_str::<Value>("[www.example.com, example.net]").unwrap()
+        );
+        // Only the alias with a zone can be validated automatically
+        let validation_options = certificate["DomainValidationOptions"]
+            .as_sequence()
+            .unwrap();
+        assert_eq!(validation_options.len(), 2);
+        assert_eq!(validation_options[1]["HostedZoneId"], "Z1");
+
+        let aliases = distribution_config(&template)["Aliases"]
+            .as_sequence()
+            .unwrap();
+        assert_eq!(aliases.len(), 3);
+        assert_eq!(aliases[1], "www.example.com");
+        assert_eq!(aliases[2], "example.net");
+
+        let record = &template["Resources"]["StaticWebsiteAliasRecordWwwExampleCom"]["Properties"];
+        assert_eq!(record["Name"], "www.example.com.");
+        assert_eq!(record["HostedZoneId"], "Z1");
+        assert_eq!(
+            template["Resources"]["StaticWebsiteAliasRecordIPv6WwwExampleCom"]["Properties"]
+                ["Type"],
+            "AAAA"
+        );
+        assert!(template["Resources"]
+            .get("StaticWebsiteAliasRecordExampleNet")
+            .is_none());
+    }
+
+    #[test]
+    fn clashing_aliases_are_rejected() {
+        let config = SiteConfig {
+            aliases: vec![
+                alias("www.example.com", None),
+                alias("www.ex-ample.com", None),
+            ],
+            ..SiteConfig::default()
+        };
+        assert!(matches!(
+            render_template(&domain(), &config),
+            Err(Error::InvalidAlias { .. })
+        ));
+    }
+
+    #[test]
+    fn canonical_redirect_applies_to_every_cache_behavior() {
+        let template = render(&SiteConfig {
+            aliases: vec![alias("www.example.com", Some("Z1"))],
+            redirect_to_canonical: true,
+            cache_behaviors: vec!["/api/*=disabled".parse().unwrap()],
+            ..SiteConfig::default()
+        });
+
+        let function = &template["Resources"][REDIRECT_FUNCTION_LOGICAL_ID]["Properties"];
+        assert_eq!(function["Name"], "StaticSite-redirect-example-com");
+        assert!(function["FunctionCode"]
+            .as_str()
+            .unwrap()
+            .contains("'example.com'"));
+
+        let config = distribution_config(&template);
+        for behavior in [
+            &config["DefaultCacheBehavior"],
+            &config["CacheBehaviors"][0],
+        ] {
+            assert_eq!(
+                behavior["FunctionAssociations"][0]["EventType"],
+                "viewer-request"
+            );
+        }
+    }
+
+    #[test]
+    fn canonical_redirect_needs_aliases() {
+        let template = render(&SiteConfig {
+            redirect_to_canonical: true,
+            ..SiteConfig::default()
+        });
+        assert!(template["Resources"]
+            .get(REDIRECT_FUNCTION_LOGICAL_ID)
+            .is_none());
+    }
+
+    #[test]
+    fn caa_records_come_before_the_certificate() {
+        let template = render(&SiteConfig {
+            aliases: vec![alias("www.example.com", Some("Z1"))],
+            caa: Some(CaaPolicy {
+                issuers: vec!["letsencrypt.org".to_string()],
+                iodef: None,
+            }),
+            ..SiteConfig::default()
+        });
+
+        assert_eq!(
+            template["Resources"][CERTIFICATE_LOGICAL_ID]["DependsOn"],
+            serde_yaml::from_str::<Value>(
+                "[StaticWebsiteCaaRecord, StaticWebsiteCaaRecordWwwExampleCom]"
+            )
+            .unwrap()
+        );
+        let values = template["Resources"]["StaticWebsiteCaaRecord"]["Properties"]
+            ["ResourceRecords"]
+            .as_sequence()
+            .unwrap();
+        assert_eq!(values.len(), 5);
+        assert_eq!(values[4], "0 issue \"letsencrypt.org\"");
+    }
+
+    #[test]
+    fn external_dns_has_no_route53() {
+        let template = render(&SiteConfig {
+            external_dns: true,
+            caa: Some(CaaPolicy::default()),
+            ..SiteConfig::default()
+        });
+
+        assert!(template["Parameters"].get("HostedZoneId").is_none());
+        for (_, resource) in template["Resources"].as_mapping().unwrap() {
+            assert_ne!(resource["Type"], "AWS::Route53::RecordSet");
+        }
+        assert!(template["Resources"][CERTIFICATE_LOGICAL_ID]["Properties"]
+            .get("DomainValidationOptions")
+            .is_none());
+    }
+
+    #[test]
+    fn separate_certificate() {
+        let config = SiteConfig {
+            caa: Some(CaaPolicy::default()),
+            separate_certificate: true,
+            ..SiteConfig::default()
+        };
+
+        let site = render(&config);
+        assert!(site["Resources"].get(CERTIFICATE_LOGICAL_ID).is_none());
+        assert!(site["Resources"].get("StaticWebsiteCaaRecord").is_none());
+        assert!(site["Parameters"]["CertificateArn"].is_mapping());
+        assert_eq!(
+            reference_to(&distribution_config(&site)["ViewerCertificate"]["AcmCertificateArn"]),
+            Some("CertificateArn")
+        );
+
+        let certificate: Value =
+            serde_yaml::from_str(&render_certificate_template(&config).unwrap().to_yaml()).unwrap();
+        assert_eq!(
+            resource_ids(&certificate),
+            vec!["StaticWebsiteCaaRecord", CERTIFICATE_LOGICAL_ID]
+        );
+        assert_eq!(
+            reference_to(&certificate["Outputs"]["CertificateArn"]["Value"]),
+            Some(CERTIFICATE_LOGICAL_ID)
+        );
+        assert_references_resolve(&certificate);
+    }
+
+    #[test]
+    fn cache_behaviors() {
+        let template = render(&SiteConfig {
+            cache_behaviors: vec![
+                "/api/*=disabled".parse().unwrap(),
+                "/assets/*=optimized".parse().unwrap(),
+                "/feeds/*=08627262-05a9-4f76-9ded-b50ca2e3a84f"
+                    .parse()
+                    .unwrap(),
+            ],
+            ..SiteConfig::default()
+        });
+
+        assert_eq!(
+            distribution_config(&template)["DefaultCacheBehavior"]["ViewerProtocolPolicy"],
+            "allow-all"
+        );
+        let behaviors = distribution_config(&template)["CacheBehaviors"]
+            .as_sequence()
+            .unwrap();
+        let rendered: Vec<(&str, &str)> = behaviors
+            .iter()
+            .map(|b| {
+                assert_eq!(b["TargetOriginId"], ORIGIN_ID);
+                assert_eq!(b["ViewerProtocolPolicy"], "allow-all");
+                (
+                    b["PathPattern"].as_str().unwrap(),
+                    b["CachePolicyId"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            rendered,
+            vec![
+                ("/api/*", CachePolicy::Disabled.id()),
+                ("/assets/*", CachePolicy::Optimized.id()),
+                ("/feeds/*", "08627262-05a9-4f76-9ded-b50ca2e3a84f"),
+            ]
+        );
+    }
+
+    #[test]
+    fn plain_http_can_be_redirected() {
+        let template = render(&SiteConfig {
+            cache_behaviors: vec!["/api/*=disabled".parse().unwrap()],
+            redirect_to_https: true,
+            ..SiteConfig::default()
+        });
+
+        let distribution_config = distribution_config(&template);
+        assert_eq!(
+            distribution_config["DefaultCacheBehavior"]["ViewerProtocolPolicy"],
+            "redirect-to-https"
+        );
+        assert_eq!(
+            distribution_config["CacheBehaviors"][0]["ViewerProtocolPolicy"],
+            "redirect-to-https"
+        );
+    }
+
+    #[test]
+    fn invalid_cache_behaviors_are_rejected() {
+        assert!("/api/*".parse::<CacheBehavior>().is_err());
+        assert!("/api/*=".parse::<CacheBehavior>().is_err());
+        assert!("/api/*=my-policy-id".parse::<CacheBehavior>().is_err());
+        assert!("/api/*=08627262-05a9-4f76-9ded-b50ca2e3a84g"
+            .parse::<CacheBehavior>()
+            .is_err());
+        assert!("/api/*=08627262-05a9-4f76-9ded-b50ca2e3a84"
+            .parse::<CacheBehavior>()
+            .is_err());
+
+        for patterns in [vec!["*=disabled"], vec!["/a/*=disabled", "/a/*=optimized"]] {
+            let config = SiteConfig {
+                cache_behaviors: patterns.iter().map(|p| p.parse().unwrap()).collect(),
+                ..SiteConfig::default()
+            };
+            assert!(matches!(
+                config.validate(),
+                Err(Error::InvalidSiteConfig { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn error_pages() {
+        let template = render(&SiteConfig {
+            error_pages: vec![
+                "404=/index.html:200".parse().unwrap(),
+                "403=/403.html".parse().unwrap(),
+            ],
+            ..SiteConfig::default()
+        });
+
+        let responses: Value = serde_yaml::from_str(
+            "
+            - ErrorCode: 404
+              ResponsePagePath: /index.html
+              ResponseCode: 200
+            - ErrorCode: 403
+              ResponsePagePath: /403.html
+              ResponseCode: 403
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            distribution_config(&template)["CustomErrorResponses"],
+            responses
+        );
+    }
+
+    #[test]
+    fn invalid_error_pages_are_rejected() {
+        assert!("404".parse::<ErrorPage>().is_err());
+        assert!("oops=/404.html".parse::<ErrorPage>().is_err());
+        assert!("404=/404.html:oops".parse::<ErrorPage>().is_err());
+
+        for pages in [
+            vec!["200=/index.html"],
+            vec!["404=/index.html:302"],
+            vec!["404=index.html"],
+            vec!["404=/a.html", "404=/b.html"],
+        ] {
+            let config = SiteConfig {
+                error_pages: pages.iter().map(|p| p.parse().unwrap()).collect(),
+                ..SiteConfig::default()
+            };
+            assert!(
+                matches!(config.validate(), Err(Error::InvalidSiteConfig { .. })),
+                "{:?}",
+                pages
+            );
+        }
+    }
+
+    #[test]
+    fn response_headers() {
+        let template = render(&SiteConfig {
+            response_headers: Some(ResponseHeaders {
+                security_headers: true,
+                custom: vec!["Permissions-Policy: camera=()".parse().unwrap()],
+            }),
+            ..SiteConfig::default()
+        });
+
+        let policy = &template["Resources"][RESPONSE_HEADERS_POLICY_LOGICAL_ID]["Properties"]
+            ["ResponseHeadersPolicyConfig"];
+        assert_eq!(policy["Name"], "StaticSite-headers-example-com");
+        assert_eq!(
+            policy["SecurityHeadersConfig"]["StrictTransportSecurity"]["AccessControlMaxAgeSec"],
+            63072000
+        );
+        let header = &policy["CustomHeadersConfig"]["Items"][0];
+        assert_eq!(header["Header"], "Permissions-Policy");
+        assert_eq!(header["Value"], "camera=()");
+
+        assert_eq!(
+            reference_to(
+                &distribution_config(&template)["DefaultCacheBehavior"]["ResponseHeadersPolicyId"]
+            ),
+            Some(RESPONSE_HEADERS_POLICY_LOGICAL_ID)
+        );
+    }
+
+    #[test]
+    fn invalid_response_headers_are_rejected() {
+        assert!("Permissions-Policy".parse::<CustomHeader>().is_err());
+
+        for (security_headers, custom) in [
+            (false, vec![]),
+            (true, vec!["X-Frame-Options: DENY"]),
+            (false, vec!["Bad Header: value"]),
+            (false, vec!["X-Empty:"]),
+            (false, vec!["X-Twice: 1", "x-twice: 2"]),
+        ] {
+            let config = SiteConfig {
+                response_headers: Some(ResponseHeaders {
+                    security_headers,
+                    custom: custom.iter().map(|h| h.parse().unwrap()).collect(),
+                }),
+                ..SiteConfig::default()
+            };
+            assert!(
+                matches!(config.validate(), Err(Error::InvalidSiteConfig { .. })),
+                "{:?}",
+                custom
+            );
+        }
+    }
+
+    #[test]
+    fn logging_can_be_turned_off() {
+        let template = render(&SiteConfig {
+            logging: LoggingConfig {
+                enabled: false,
+                retention_days: 90,
+            },
+            ..SiteConfig::default()
+        });
+
+        assert!(template["Resources"].get("LoggingBucket").is_none());
+        assert!(template["Resources"]["WebsiteBucket"]["Properties"]
+            .get("LoggingConfiguration")
+            .is_none());
+        assert!(distribution_config(&template).get("Logging").is_none());
+        assert_references_resolve(&template);
+    }
+
+    #[test]
+    fn log_retention() {
+        let template = render(&SiteConfig {
+            logging: LoggingConfig {
+                enabled: true,
+                retention_days: 30,
+            },
+            ..SiteConfig::default()
+        });
+
+        let rule = &template["Resources"]["LoggingBucket"]["Properties"]["LifecycleConfiguration"]
+            ["Rules"][0];
+        assert_eq!(rule["ExpirationInDays"], 30);
+        assert_eq!(rule["Id"], "ExpireLogsAfter30Days");
+    }
+
+    #[test]
+    fn web_acl() {
+        let template = render(&full_config());
+        assert_eq!(
+            distribution_config(&template)["WebACLId"],
+            "arn:aws:wafv2:us-east-1:123456789012:global/webacl/site/abc"
+        );
+
+        let config = SiteConfig {
+            web_acl_arn: Some(
+                "arn:aws:wafv2:eu-west-1:123456789012:regional/webacl/site/abc".to_string(),
+            ),
+            ..SiteConfig::default()
+        };
+        assert!(matches!(
+            config.validate(),
+            Err(Error::InvalidSiteConfig { .. })
+        ));
+    }
+
+    #[test]
+    fn json_expands_intrinsic_functions() {
+        let json: serde_json::Value = serde_json::from_str(
+            &render_template(&domain(), &SiteConfig::default())
+                .unwrap()
+                .to_json(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            json["Outputs"]["StaticWebsiteCfnEndpoint"]["Value"],
+            serde_json::json!({ "Fn::GetAtt": ["CloudfrontDistribution", "DomainName"] })
+        );
+        assert_eq!(
+            json["Outputs"]["Distribution"]["Value"],
+            serde_json::json!({ "Ref": "CloudfrontDistribution" })
+        );
+        assert_eq!(
+            json["Resources"]["StaticWebsiteAliasRecord"]["Properties"]["AliasTarget"]
+                ["HostedZoneId"],
+            CLOUDFRONT_HOSTED_ZONE_ID
+        );
+        assert_eq!(
+            json["Resources"]["WebsiteBucket"]["Properties"]["LifecycleConfiguration"]["Rules"][0]
+                ["NoncurrentVersionExpiration"]["NoncurrentDays"],
+            30
+        );
+    }
+
+    #[test]
+    fn stamps_resources_for_repair() {
+        let template = render_template(&domain(), &SiteConfig::default())
+            .unwrap()
+            .to_yaml();
+        let resources: Vec<String> = [
+            "CloudfrontDistribution",
+            "StaticWebsiteOAI",
+            "StaticWebsiteBucketPolicy",
+            "WebsiteBucket",
+        ]
+        .iter()
+        .map(|id| id.to_string())
+        .collect();
+
+        let (stamped, unrepairable) = stamp_for_repair(&template, &resources, "1234").unwrap();
+        let stamped: Value = serde_yaml::from_str(&stamped).unwrap();
+        assert_eq!(
+            distribution_config(&stamped)["Comment"],
+            "Repaired by staticwebsite-cli at 1234"
+        );
+        assert_eq!(
+            stamped["Resources"]["StaticWebsiteOAI"]["Properties"]
+                ["CloudFrontOriginAccessIdentityConfig"]["Comment"],
+            "Repaired by staticwebsite-cli at 1234"
+        );
+        assert_eq!(
+            stamped["Resources"]["StaticWebsiteBucketPolicy"]["Properties"]["PolicyDocument"]["Id"],
+            "repaired-1234"
+        );
+
+        // A bucket's tags changing wouldn't re-apply the rest of its settings
+        assert_eq!(unrepairable, vec!["WebsiteBucket".to_string()]);
+        let original: Value = serde_yaml::from_str(&template).unwrap();
+        assert_eq!(
+            stamped["Resources"]["WebsiteBucket"],
+            original["Resources"]["WebsiteBucket"]
+        );
+    }
+}
@@ -0,0 +1,257 @@
+use serde_yaml::value::{Tag, TaggedValue};
+use serde_yaml::{Mapping, Value};
+
+///
+/// A CloudFormation template, built up in code rather than patched together from YAML. Each
+/// section keeps its entries in the order they were added, so the rendered template reads in a
+/// sensible order.
+///
+#[derive(Clone, Debug, Default)]
+pub struct Template {
+    pub parameters: Vec<(String, Parameter)>,
+    pub resources: Vec<(String, Resource)>,
+    pub outputs: Vec<(String, Value)>,
+}
+
+///
+/// A template parameter. All of ours are strings.
+///
+#[derive(Clone, Debug)]
+pub struct Parameter {
+    pub description: String,
+}
+
+///
+/// A resource in the template. The properties are left as YAML, as they're whatever the resource
+/// type says they are.
+///
+#[derive(Clone, Debug)]
+pub struct Resource {
+    pub resource_type: String,
+    pub properties: Value,
+    pub depends_on: Vec<String>,
+    pub deletion_policy: Option<String>,
+    pub update_replace_policy: Option<String>,
+}
+
+impl Resource {
+    pub fn new(resource_type: &str, properties: Value) -> Self {
+        Resource {
+            resource_type: resource_type.to_string(),
+            properties,
+            depends_on: vec![],
+            deletion_policy: None,
+            update_replace_policy: None,
+        }
+    }
+}
+
+impl Template {
+    pub fn add_parameter(&mut self, name: &str, description: &str) {
+        self.parameters.push((
+            name.to_string(),
+            Parameter {
+                description: description.to_string(),
+            },
+        ));
+    }
+
+    pub fn add_resource(&mut self, logical_id: &str, resource: Resource) {
+        self.resources.push((logical_id.to_string(), resource));
+    }
+
+    pub fn add_output(&mut self, name: &str, value: Value) {
+        self.outputs.push((name.to_string(), value));
+    }
+
+    ///
+    /// The template as YAML, ready to hand to CloudFormation.
+    ///
+    pub fn to_yaml(&self) -> String {
+        serde_yaml::to_string(&self.to_value()).expect("a YAML value should serialize")
+    }
+
+    ///
+    /// The template as JSON. JSON has no tags, so intrinsic functions are written out in full -
+    /// `!Ref` as `{"Ref": ...}`, `!GetAtt` as `{"Fn::GetAtt": [...]}` and so on.
+    ///
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&to_json(&self.to_value())).expect("JSON should serialize")
+            + "\n"
+    }
+
+    ///
+    /// The template as a YAML value, with any empty sections left out.
+    ///
+    pub fn to_value(&self) -> Value {
+        let mut template = Mapping::new();
+
+        let parameters: Vec<(&str, Value)> = self
+            .parameters
+            .iter()
+            .map(|(name, parameter)| {
+                (
+                    name.as_str(),
+                    mapping(vec![
+                        ("Description", string(&parameter.description)),
+                        ("Type", string("String")),
+                    ]),
+                )
+            })
+            .collect();
+        let resources: Vec<(&str, Value)> = self
+            .resources
+            .iter()
+            .map(|(logical_id, resource)| (logical_id.as_str(), resource_value(resource)))
+            .collect();
+        let outputs: Vec<(&str, Value)> = self
+            .outputs
+            .iter()
+            .map(|(name, value)| (name.as_str(), mapping(vec![("Value", value.clone())])))
+            .collect();
+
+        for (section, entries) in [
+            ("Parameters", parameters),
+            ("Resources", resources),
+            ("Outputs", outputs),
+        ] {
+            if !entries.is_empty() {
+                template.insert(string(section), mapping(entries));
+            }
+        }
+
+        Value::Mapping(template)
+    }
+}
+
+fn resource_value(resource: &Resource) -> Value {
+    let mut entries = vec![("Type", string(&resource.resource_type))];
+    if !resource.depends_on.is_empty() {
+        entries.push((
+            "DependsOn",
+            Value::Sequence(resource.depends_on.iter().map(|id| string(id)).collect()),
+        ));
+    }
+    if let Some(policy) = &resource.update_replace_policy {
+        entries.push(("UpdateReplacePolicy", string(policy)));
+    }
+    if let Some(policy) = &resource.deletion_policy {
+        entries.push(("DeletionPolicy", string(policy)));
+    }
+    entries.push(("Properties", resource.properties.clone()));
+    mapping(entries)
+}
+
+///
+/// Converts a YAML template value to JSON, expanding short-form intrinsic functions.
+///
+fn to_json(value: &Value) -> serde_json::Value {
+    match value {
+        Value::Null => serde_json::Value::Null,
+        Value::Bool(b) => serde_json::Value::Bool(*b),
+        Value::Number(n) => {
+            if let Some(n) = n.as_u64() {
+                n.into()
+            } else if let Some(n) = n.as_i64() {
+                n.into()
+            } else {
+                n.as_f64().map(|n| n.into()).unwrap_or_default()
+            }
+        }
+        Value::String(s) => serde_json::Value::String(s.clone()),
+        Value::Sequence(values) => values.iter().map(to_json).collect(),
+        Value::Mapping(mapping) => mapping
+            .iter()
+            .map(|(key, value)| {
+                let key = match key {
+                    Value::String(key) => key.clone(),
+                    key => serde_yaml::to_string(key)
+                        .expect("a YAML value should serialize")
+                        .trim_end()
+                        .to_string(),
+                };
+                (key, to_json(value))
+            })
+            .collect::<serde_json::Map<String, serde_json::Value>>()
+            .into(),
+        Value::Tagged(tagged) => {
+            let function = tagged.tag.to_string().trim_start_matches('!').to_string();
+            let argument = match (function.as_str(), &tagged.value) {
+                // The short form joins the resource and attribute with a dot
+                ("GetAtt", Value::String(target)) => match target.split_once('.') {
+                    Some((logical_id, attribute)) => serde_json::json!([logical_id, attribute]),
+                    None => serde_json::Value::String(target.clone()),
+                },
+                (_, value) => to_json(value),
+            };
+            let key = match function.as_str() {
+                "Ref" | "Condition" => function,
+                _ => format!("Fn::{}", function),
+            };
+            serde_json::json!({ key: argument })
+        }
+    }
+}
+
+///
+/// `!Ref name`
+///
+pub fn reference(name: &str) -> Value {
+    tagged("Ref", string(name))
+}
+
+///
+/// `!GetAtt logical_id.attribute`
+///
+pub fn get_att(logical_id: &str, attribute: &str) -> Value {
+    tagged("GetAtt", string(&format!("{}.{}", logical_id, attribute)))
+}
+
+///
+/// `!Sub template`
+///
+pub fn sub(template: &str) -> Value {
+    tagged("Sub", string(template))
+}
+
+///
+/// `!Join [delimiter, parts]`
+///
+pub fn join(delimiter: &str, parts: Vec<Value>) -> Value {
+    tagged(
+        "Join",
+        Value::Sequence(vec![string(delimiter), Value::Sequence(parts)]),
+    )
+}
+
+pub fn string(value: &str) -> Value {
+    Value::String(value.to_string())
+}
+
+pub fn mapping(entries: Vec<(&str, Value)>) -> Value {
+    let mut mapping = Mapping::new();
+    for (key, value) in entries {
+        mapping.insert(string(key), value);
+    }
+    Value::Mapping(mapping)
+}
+
+pub fn tagged(tag: &str, value: Value) -> Value {
+    Value::Tagged(Box::new(TaggedValue {
+        tag: Tag::new(tag),
+        value,
+    }))
+}
+
+///
+/// Appends `item` to the list under `key` in `parent`, creating the list if needed.
+///
+pub fn push(parent: &mut Value, key: &str, item: Value) {
+    let list = &mut parent[key];
+    if !list.is_sequence() {
+        *list = Value::Sequence(vec![]);
+    }
+    list.as_sequence_mut()
+        .expect("we just made this a sequence")
+        .push(item);
+}
@@ -0,0 +1,440 @@
+use crate::Error;
+use serde_yaml::Value;
+
+/// The outputs the CLI reads from a site's stack once it has deployed
+pub const SITE_OUTPUTS: [&str; 2] = ["StaticWebsiteBucket", "Distribution"];
+
+/// The outputs the CLI reads from a certificate's stack once it has deployed
+pub const CERTIFICATE_OUTPUTS: [&str; 1] = ["CertificateArn"];
+
+/// The pseudo parameters CloudFormation declares in every template
+const PSEUDO_PARAMETERS: [&str; 8] = [
+    "AWS::AccountId",
+    "AWS::NoValue",
+    "AWS::NotificationARNs",
+    "AWS::Partition",
+    "AWS::Region",
+    "AWS::StackId",
+    "AWS::StackName",
+    "AWS::URLSuffix",
+];
+
+///
+/// Checks a template is something we can deploy before handing it to CloudFormation, which would
+/// otherwise only tell us once the stack had started creating. We check that:
+///
+/// * it has each of `required_outputs`,
+/// * every `Ref`, `GetAtt`, `Sub`, `FindInMap` and `DependsOn` refers to something it declares,
+/// * every resource has a well-formed type,
+/// * every parameter without a default is one of `supplied_parameters`.
+///
+/// Each problem found is reported along with its path in the template, e.g.
+/// `Resources.CloudfrontDistribution.Properties.DistributionConfig`. Returns the names of the
+/// parameters the template declares.
+///
+pub fn check_template(
+    template_body: &str,
+    required_outputs: &[&str],
+    supplied_parameters: &[String],
+) -> Result<Vec<String>, Error> {
+    let template: Value = serde_yaml::from_str(template_body)?;
+    let mut problems: Vec<String> = vec![];
+
+    let parameters = names(&template["Parameters"]);
+    let resources = names(&template["Resources"]);
+    let mappings = names(&template["Mappings"]);
+
+    for name in &parameters {
+        let parameter = &template["Parameters"][name.as_str()];
+        if !parameter["Type"].is_string() {
+            problems.push(format!("Parameters.{}: has no Type", name));
+        }
+        if parameter.get("Default").is_none() && !supplied_parameters.contains(name) {
+            problems.push(format!(
+                "Parameters.{}: has no Default, and isn't one the CLI supplies",
+                name
+            ));
+        }
+    }
+
+    if resources.is_empty() {
+        problems.push("Resources: the template has no resources".to_string());
+    }
+    for name in &resources {
+        let resource = &template["Resources"][name.as_str()];
+        match resource["Type"].as_str() {
+            Some(resource_type) if valid_resource_type(resource_type) => {}
+            Some(resource_type) => problems.push(format!(
+                "Resources.{}.Type: {} isn't a resource type",
+                name, resource_type
+            )),
+            None => problems.push(format!("Resources.{}: has no Type", name)),
+        }
+
+        let depends_on: Vec<&Value> = match &resource["DependsOn"] {
+            Value::Sequence(ids) => ids.iter().collect(),
+            Value::Null => vec![],
+            id => vec![id],
+        };
+        for id in depends_on {
+            if !id
+                .as_str()
+                .is_some_and(|id| resources.iter().any(|r| r == id))
+            {
+                problems.push(format!(
+                    "Resources.{}.DependsOn: {} isn't a resource",
+                    name,
+                    describe(id)
+                ));
+            }
+        }
+    }
+
+    for output in required_outputs {
+        if template["Outputs"][*output].get("Value").is_none() {
+            problems.push(format!(
+                "Outputs.{}: missing, and the CLI relies on it",
+                output
+            ));
+        }
+    }
+
+    let declared = Declared {
+        parameters: &parameters,
+        resources: &resources,
+        mappings: &mappings,
+    };
+    for section in ["Resources", "Outputs"] {
+        check_references(&template[section], section, &declared, &mut problems);
+    }
+
+    if problems.is_empty() {
+        Ok(parameters)
+    } else {
+        Err(Error::InvalidTemplate { problems })
+    }
+}
+
+///
+/// What a template declares, and so what it can refer to.
+///
+struct Declared<'a> {
+    parameters: &'a [String],
+    resources: &'a [String],
+    mappings: &'a [String],
+}
+
+impl Declared<'_> {
+    fn can_ref(&self, name: &str) -> bool {
+        PSEUDO_PARAMETERS.contains(&name)
+            || self.parameters.iter().any(|p| p == name)
+            || self.resources.iter().any(|r| r == name)
+    }
+
+    fn has_resource(&self, name: &str) -> bool {
+        self.resources.iter().any(|r| r == name)
+    }
+}
+
+///
+/// Walks `value`, checking every intrinsic function we find refers to something the template
+/// declares. Both the short (`!Ref`) and long (`Ref:`) forms are understood.
+///
+fn check_references(value: &Value, path: &str, declared: &Declared, problems: &mut Vec<String>) {
+    let function = match value {
+        Value::Tagged(tagged) => {
+            let name = tagged.tag.to_string();
+            let name = name.trim_start_matches('!');
+            let name = match name {
+                "Ref" | "Condition" => name.to_string(),
+                _ => format!("Fn::{}", name),
+            };
+            Some((name, &tagged.value))
+        }
+        Value::Mapping(mapping) if mapping.len() == 1 => {
+            let (key, argument) = mapping.iter().next().expect("the mapping has an entry");
+            key.as_str()
+                .filter(|key| *key == "Ref" || key.starts_with("Fn::"))
+                .map(|key| (key.to_string(), argument))
+        }
+        _ => None,
+    };
+
+    if let Some((function, argument)) = function {
+        let path = format!("{}.{}", path, function);
+        match function.as_str() {
+            "Ref" => match argument.as_str() {
+                Some(name) if declared.can_ref(name) => {}
+                _ => problems.push(format!(
+                    "{}: {} isn't a parameter or resource",
+                    path,
+                    describe(argument)
+                )),
+            },
+            "Fn::GetAtt" => {
+                let logical_id = match argument {
+                    Value::String(target) => target.split('.').next(),
+                    Value::Sequence(parts) => parts.first().and_then(|p| p.as_str()),
+                    _ => None,
+                };
+                if !logical_id.is_some_and(|id| declared.has_resource(id)) {
+                    problems.push(format!("{}: {} isn't a resource", path, describe(argument)));
+                }
+            }
+            "Fn::Sub" => {
+                let (template, variables) = match argument {
+                    Value::Sequence(parts) => (parts.first(), parts.get(1)),
+                    template => (Some(template), None),
+                };
+                let local = variables.map(names).unwrap_or_default();
+                if let Some(template) = template.and_then(|t| t.as_str()) {
+                    for variable in sub_variables(template) {
+                        let name = variable.split('.').next().unwrap_or_default();
+                        let resolves = local.iter().any(|l| l == &variable)
+                            || if variable.contains('.') {
+                                declared.has_resource(name)
+                            } else {
+                                declared.can_ref(name)
+                            };
+                        if !resolves {
+                            problems.push(format!(
+                                "{}: ${{{}}} isn't a parameter or resource",
+                                path, variable
+                            ));
+                        }
+                    }
+                }
+            }
+            "Fn::FindInMap" => {
+                let map = argument
+                    .as_sequence()
+                    .and_then(|parts| parts.first())
+                    .and_then(|map| map.as_str());
+                if let Some(map) = map {
+                    if !declared.mappings.iter().any(|m| m == map) {
+                        problems.push(format!("{}: {} isn't a mapping", path, map));
+                    }
+                }
+            }
+            _ => {}
+        }
+        check_references(argument, &path, declared, problems);
+        return;
+    }
+
+    match value {
+        Value::Sequence(values) => {
+            for (i, value) in values.iter().enumerate() {
+                check_references(value, &format!("{}[{}]", path, i), declared, problems);
+            }
+        }
+        Value::Mapping(mapping) => {
+            for (key, value) in mapping {
+                let key = key.as_str().map(|k| k.to_string()).unwrap_or(describe(key));
+                check_references(value, &format!("{}.{}", path, key), declared, problems);
+            }
+        }
+        _ => {}
+    }
+}
+
+///
+/// The variables a `Sub` template interpolates - `${Name}` or `${Resource.Attribute}`. `${!Name}`
+/// is a literal, and is skipped.
+///
+fn sub_variables(template: &str) -> Vec<String> {
+    let mut variables = vec![];
+    let mut rest = template;
+    while let Some(start) = rest.find("${") {
+        rest = &rest[start + 2..];
+        let Some(end) = rest.find('}') else {
+            break;
+        };
+        let variable = &rest[..end];
+        if !variable.starts_with('!') {
+            variables.push(variable.trim().to_string());
+        }
+        rest = &rest[end + 1..];
+    }
+    variables
+}
+
+///
+/// Checks a type looks like `AWS::Service::Resource`, or a `Custom::Name` custom resource.
+///
+fn valid_resource_type(resource_type: &str) -> bool {
+    let parts: Vec<&str> = resource_type.split("::").collect();
+    let well_formed = parts.iter().all(|part| {
+        !part.is_empty()
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '@' || c == '-')
+    });
+    well_formed
+        && match parts[0] {
+            "AWS" | "Alexa" => parts.len() == 3,
+            "Custom" => parts.len() == 2,
+            // Registry and module types are named by whoever published them
+            _ => parts.len() >= 3,
+        }
+}
+
+///
+/// The keys of a template section.
+///
+fn names(section: &Value) -> Vec<String> {
+    section
+        .as_mapping()
+        .map(|mapping| {
+            mapping
+                .keys()
+                .filter_map(|key| key.as_str().map(|key| key.to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn describe(value: &Value) -> String {
+    serde_yaml::to_string(value)
+        .expect("a YAML value should serialize")
+        .trim_end()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::{render_certificate_template, render_template, SiteConfig};
+
+    fn supplied() -> Vec<String> {
+        ["DomainName", "HostedZoneId", "CertificateArn"]
+            .iter()
+            .map(|p| p.to_string())
+            .collect()
+    }
+
+    fn problems(template_body: &str, required_outputs: &[&str]) -> Vec<String> {
+        match check_template(template_body, required_outputs, &supplied()) {
+            Err(Error::InvalidTemplate { problems }) => problems,
+            other => panic!("expected problems, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn generated_templates_pass() {
+        let config = SiteConfig::default();
+        let site = render_template("example.com", &config).unwrap();
+        let parameters = check_template(&site.to_yaml(), &SITE_OUTPUTS, &supplied()).unwrap();
+        assert!(parameters.contains(&"DomainName".to_string()));
+        check_template(&site.to_json(), &SITE_OUTPUTS, &supplied()).unwrap();
+
+        let config = SiteConfig {
+            separate_certificate: true,
+            ..SiteConfig::default()
+        };
+        let certificate = render_certificate_template(&config).unwrap();
+        check_template(&certificate.to_yaml(), &CERTIFICATE_OUTPUTS, &supplied()).unwrap();
+    }
+
+    #[test]
+    fn missing_outputs_are_reported() {
+        let template = "
+Resources:
+  WebsiteBucket:
+    Type: AWS::S3::Bucket
+Outputs:
+  StaticWebsiteBucket:
+    Value: !Ref WebsiteBucket
+";
+        assert_eq!(
+            problems(template, &SITE_OUTPUTS),
+            vec!["Outputs.Distribution: missing, and the CLI relies on it"]
+        );
+    }
+
+    #[test]
+    fn dangling_references_are_reported_with_their_path() {
+        let template = "
+Resources:
+  WebsiteBucket:
+    Type: AWS::S3::Bucket
+    DependsOn: Certificate
+  Distribution:
+    Type: AWS::CloudFront::Distribution
+    Properties:
+      DistributionConfig:
+        Origins:
+          - DomainName: !GetAtt Bucket.RegionalDomainName
+        Aliases:
+          - !Sub '${Domain}'
+          - !Sub ['${Name}.${AWS::Region}', { Name: !Ref DomainName }]
+          - Fn::FindInMap: [Zones, !Ref AWS::Partition, Id]
+Outputs:
+  Missing:
+    Value: { Ref: Nowhere }
+";
+        assert_eq!(
+            problems(template, &[]),
+            vec![
+                "Resources.WebsiteBucket.DependsOn: Certificate isn't a resource",
+                "Resources.Distribution.Properties.DistributionConfig.Origins[0].DomainName.Fn::GetAtt: \
+                 Bucket.RegionalDomainName isn't a resource",
+                "Resources.Distribution.Properties.DistributionConfig.Aliases[0].Fn::Sub: \
+                 ${Domain} isn't a parameter or resource",
+                "Resources.Distribution.Properties.DistributionConfig.Aliases[1].Fn::Sub[1].Name.Ref: \
+                 DomainName isn't a parameter or resource",
+                "Resources.Distribution.Properties.DistributionConfig.Aliases[2].Fn::FindInMap: \
+                 Zones isn't a mapping",
+                "Outputs.Missing.Value.Ref: Nowhere isn't a parameter or resource",
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_types_and_unsupplied_parameters_are_reported() {
+        let template = "
+Parameters:
+  DomainName:
+    Type: String
+  IndexDocument:
+    Type: String
+  ErrorDocument:
+    Type: String
+    Default: error.html
+  Untyped:
+    Default: x
+Resources:
+  WebsiteBucket:
+    Type: S3 Bucket
+  Typeless:
+    Properties: {}
+  Handler:
+    Type: Custom::Handler
+";
+        assert_eq!(
+            problems(template, &[]),
+            vec![
+                "Parameters.IndexDocument: has no Default, and isn't one the CLI supplies",
+                "Parameters.Untyped: has no Type",
+                "Resources.WebsiteBucket.Type: S3 Bucket isn't a resource type",
+                "Resources.Typeless: has no Type",
+            ]
+        );
+    }
+
+    #[test]
+    fn unparseable_templates_are_reported() {
+        assert!(matches!(
+            check_template("Resources: [", &[], &[]),
+            Err(Error::TemplateParseError { .. })
+        ));
+    }
+
+    #[test]
+    fn sub_variables_skip_literals() {
+        assert_eq!(
+            sub_variables("${A}-${!Literal}-${B.Arn}"),
+            vec!["A".to_string(), "B.Arn".to_string()]
+        );
+    }
+}
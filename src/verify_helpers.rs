@@ -0,0 +1,229 @@
+use crate::Error;
+use hyper::client::HttpConnector;
+use hyper::{Body, Client, Uri};
+use hyper_rustls::HttpsConnector;
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+use tokio::net::lookup_host;
+use tokio::time::timeout;
+use tracing::{info, warn};
+
+/// How many of the uploaded files we fetch, in addition to the site root
+const SAMPLED_KEYS: usize = 5;
+
+/// How long we give any single DNS lookup or HTTPS request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+///
+/// Checks the deployed site is reachable. For each of `names` we check that:
+///
+/// * it resolves to IPv4 addresses,
+/// * those addresses are the distribution's - i.e. they overlap with what `endpoint` resolves to,
+/// * `/` and a sample of the uploaded `keys` can be fetched over HTTPS. The TLS handshake verifies
+///   that the certificate covers the name.
+///
+/// DNS changes take a while to propagate, so the checks are retried until they all pass or
+/// `deadline` passes, at which point the outstanding failures are returned in the error.
+///
+/// A name without IPv6 addresses is only warned about, as resolvers on networks without IPv6
+/// often don't return them.
+///
+pub async fn verify_site(
+    names: &[String],
+    endpoint: &str,
+    keys: &[String],
+    deadline: Duration,
+) -> Result<(), Error> {
+    let started = Instant::now();
+    let client = https_client();
+    let paths = sample_paths(keys);
+
+    loop {
+        let mut failures: Vec<String> = vec![];
+        let mut warnings: Vec<String> = vec![];
+        for name in names {
+            failures.extend(verify_name(name, endpoint, &paths, &client, &mut warnings).await);
+        }
+
+        if failures.is_empty() {
+            for warning in &warnings {
+                warn!(warning = warning, "Verification warning");
+            }
+            info!("Site verified");
+            return Ok(());
+        }
+
+        if started.elapsed() >= deadline {
+            for failure in &failures {
+                warn!(failure = failure, "Verification failed");
+            }
+            return Err(Error::VerificationFailed { failures });
+        }
+
+        info!(
+            outstanding = failures.len(),
+            first = &failures[0],
+            "Site not reachable yet; waiting for DNS to propagate"
+        );
+        tokio::time::sleep(Duration::from_secs(15)).await;
+    }
+}
+
+///
+/// Runs each of the checks against a single name, returning a description of each that failed.
+/// Checks that only merit a warning add to `warnings` instead.
+///
+async fn verify_name(
+    name: &str,
+    endpoint: &str,
+    paths: &[String],
+    client: &Client<HttpsConnector<HttpConnector>, Body>,
+    warnings: &mut Vec<String>,
+) -> Vec<String> {
+    let mut failures: Vec<String> = vec![];
+
+    let addresses = match resolve(name).await {
+        Ok(addresses) => addresses,
+        Err(reason) => return vec![format!("{}: {}", name, reason)],
+    };
+    if !addresses.iter().any(|a| a.is_ipv4()) {
+        failures.push(format!("{}: no A records", name));
+    }
+    if !addresses.iter().any(|a| a.is_ipv6()) {
+        warnings.push(format!("{}: no AAAA records", name));
+    }
+
+    match resolve(endpoint).await {
+        Ok(endpoint_addresses) => {
+            if addresses.is_disjoint(&endpoint_addresses) {
+                failures.push(format!("{}: doesn't resolve to {}", name, endpoint));
+            } else {
+                info!(
+                    name = name,
+                    endpoint = endpoint,
+                    "DNS points at distribution"
+                );
+            }
+        }
+        Err(reason) => failures.push(format!("{}: {}", endpoint, reason)),
+    }
+
+    for path in paths {
+        let url = format!("https://{}{}", name, path);
+        match fetch(&url, client).await {
+            Ok(status) if status.is_success() || status.is_redirection() => {
+                info!(url = &url, status = status.as_u16(), "Fetched");
+            }
+            Ok(status) => failures.push(format!("{}: HTTP {}", url, status.as_u16())),
+            Err(reason) => failures.push(format!("{}: {}", url, reason)),
+        }
+    }
+
+    failures
+}
+
+async fn resolve(name: &str) -> Result<HashSet<IpAddr>, String> {
+    match timeout(REQUEST_TIMEOUT, lookup_host(format!("{}:443", name))).await {
+        Ok(Ok(addresses)) => Ok(addresses.map(|a| a.ip()).collect()),
+        Ok(Err(err)) => Err(format!("DNS lookup failed: {}", err)),
+        Err(_) => Err("DNS lookup timed out".to_string()),
+    }
+}
+
+async fn fetch(
+    url: &str,
+    client: &Client<HttpsConnector<HttpConnector>, Body>,
+) -> Result<hyper::StatusCode, String> {
+    let uri: Uri = url.parse().map_err(|e| format!("invalid URL: {}", e))?;
+    match timeout(REQUEST_TIMEOUT, client.get(uri)).await {
+        Ok(Ok(response)) => Ok(response.status()),
+        Ok(Err(err)) => Err(describe_error(&err)),
+        Err(_) => Err("request timed out".to_string()),
+    }
+}
+
+///
+/// Flattens an error and its sources into one line, so TLS failures such as a certificate not
+/// covering the name aren't hidden behind a generic "error trying to connect".
+///
+fn describe_error(err: &dyn std::error::Error) -> String {
+    let mut description = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        description = format!("{}: {}", description, err);
+        source = err.source();
+    }
+    description
+}
+
+fn https_client() -> Client<HttpsConnector<HttpConnector>, Body> {
+    let connector = hyper_rustls::HttpsConnectorBuilder::new()
+        .with_native_roots()
+        .https_only()
+        .enable_http1()
+        .build();
+    Client::builder().build(connector)
+}
+
+///
+/// Picks the site root plus a handful of the uploaded keys, spread evenly across the upload,
+/// and turns them into URL paths.
+///
+fn sample_paths(keys: &[String]) -> Vec<String> {
+    let mut paths = vec!["/".to_string()];
+    let step = (keys.len() / SAMPLED_KEYS).max(1);
+    for key in keys.iter().step_by(step).take(SAMPLED_KEYS) {
+        paths.push(format!("/{}", encode_path(key)));
+    }
+    paths
+}
+
+///
+/// Percent-encodes everything in an object key other than unreserved characters and `/`.
+///
+fn encode_path(key: &str) -> String {
+    let mut encoded = String::new();
+    for byte in key.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_the_root_and_keys_spread_across_the_upload() {
+        assert_eq!(sample_paths(&[]), vec!["/"]);
+
+        let keys: Vec<String> = (0..3).map(|i| format!("{}.html", i)).collect();
+        assert_eq!(
+            sample_paths(&keys),
+            vec!["/", "/0.html", "/1.html", "/2.html"]
+        );
+
+        let keys: Vec<String> = (0..20).map(|i| format!("{}.html", i)).collect();
+        assert_eq!(
+            sample_paths(&keys),
+            vec!["/", "/0.html", "/4.html", "/8.html", "/12.html", "/16.html"]
+        );
+    }
+
+    #[test]
+    fn encodes_paths() {
+        assert_eq!(
+            encode_path("css/site-1.0_min.css~"),
+            "css/site-1.0_min.css~"
+        );
+        assert_eq!(encode_path("my page.html"), "my%20page.html");
+        assert_eq!(encode_path("a?b#c%d+e"), "a%3Fb%23c%25d%2Be");
+        assert_eq!(encode_path("café/index.html"), "caf%C3%A9/index.html");
+    }
+}